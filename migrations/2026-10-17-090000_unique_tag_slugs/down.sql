@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX tags_sound_id_slug_unique;
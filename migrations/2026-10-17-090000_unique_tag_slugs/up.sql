@@ -0,0 +1,8 @@
+-- Your SQL goes here
+-- Slugs stored before they were normalized are rewritten by the
+-- server on startup, which merges the ones that end up equal.
+DELETE FROM tags WHERE rowid NOT IN (
+    SELECT min(rowid) FROM tags GROUP BY sound_id, slug
+);
+
+CREATE UNIQUE INDEX tags_sound_id_slug_unique ON tags (sound_id, slug);
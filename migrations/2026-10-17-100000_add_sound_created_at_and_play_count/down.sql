@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX tags_slug;
+DROP INDEX sounds_name;
+
+ALTER TABLE sounds DROP COLUMN play_count;
+ALTER TABLE sounds DROP COLUMN created_at;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+ALTER TABLE sounds ADD COLUMN created_at BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE sounds ADD COLUMN play_count INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX sounds_name ON sounds (name);
+CREATE INDEX tags_slug ON tags (slug);
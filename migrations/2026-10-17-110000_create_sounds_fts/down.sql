@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER tags_fts_after_delete;
+DROP TRIGGER tags_fts_after_update;
+DROP TRIGGER tags_fts_after_insert;
+DROP TRIGGER sounds_fts_after_delete;
+DROP TRIGGER sounds_fts_after_update;
+DROP TRIGGER sounds_fts_after_insert;
+DROP TABLE sounds_fts;
@@ -0,0 +1,47 @@
+-- Your SQL goes here
+CREATE VIRTUAL TABLE sounds_fts USING fts5(
+    sound_id UNINDEXED,
+    name,
+    tags,
+    tokenize = 'unicode61'
+);
+
+INSERT INTO sounds_fts (sound_id, name, tags)
+SELECT
+    sounds.id,
+    sounds.name,
+    coalesce((SELECT group_concat(tags.slug, ' ') FROM tags WHERE tags.sound_id = sounds.id), '')
+FROM sounds;
+
+CREATE TRIGGER sounds_fts_after_insert AFTER INSERT ON sounds BEGIN
+    INSERT INTO sounds_fts (sound_id, name, tags) VALUES (new.id, new.name, '');
+END;
+
+CREATE TRIGGER sounds_fts_after_update AFTER UPDATE OF name ON sounds BEGIN
+    UPDATE sounds_fts SET name = new.name WHERE sound_id = new.id;
+END;
+
+CREATE TRIGGER sounds_fts_after_delete AFTER DELETE ON sounds BEGIN
+    DELETE FROM sounds_fts WHERE sound_id = old.id;
+END;
+
+CREATE TRIGGER tags_fts_after_insert AFTER INSERT ON tags BEGIN
+    UPDATE sounds_fts
+    SET tags = coalesce((SELECT group_concat(slug, ' ') FROM tags WHERE sound_id = new.sound_id), '')
+    WHERE sound_id = new.sound_id;
+END;
+
+CREATE TRIGGER tags_fts_after_update AFTER UPDATE ON tags BEGIN
+    UPDATE sounds_fts
+    SET tags = coalesce((SELECT group_concat(slug, ' ') FROM tags WHERE sound_id = old.sound_id), '')
+    WHERE sound_id = old.sound_id;
+    UPDATE sounds_fts
+    SET tags = coalesce((SELECT group_concat(slug, ' ') FROM tags WHERE sound_id = new.sound_id), '')
+    WHERE sound_id = new.sound_id;
+END;
+
+CREATE TRIGGER tags_fts_after_delete AFTER DELETE ON tags BEGIN
+    UPDATE sounds_fts
+    SET tags = coalesce((SELECT group_concat(slug, ' ') FROM tags WHERE sound_id = old.sound_id), '')
+    WHERE sound_id = old.sound_id;
+END;
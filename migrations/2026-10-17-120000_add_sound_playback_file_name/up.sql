@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE sounds ADD COLUMN playback_file_name TEXT;
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE sounds DROP COLUMN codec;
+ALTER TABLE sounds DROP COLUMN channels;
+ALTER TABLE sounds DROP COLUMN sample_rate;
+ALTER TABLE sounds DROP COLUMN duration_ms;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE sounds ADD COLUMN duration_ms BIGINT;
+ALTER TABLE sounds ADD COLUMN sample_rate INTEGER;
+ALTER TABLE sounds ADD COLUMN channels INTEGER;
+ALTER TABLE sounds ADD COLUMN codec TEXT;
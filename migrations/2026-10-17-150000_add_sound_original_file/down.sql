@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE sounds DROP COLUMN original_file_hash;
+ALTER TABLE sounds DROP COLUMN original_extension;
+ALTER TABLE sounds DROP COLUMN original_file_name;
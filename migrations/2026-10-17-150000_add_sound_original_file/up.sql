@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE sounds ADD COLUMN original_file_name TEXT;
+ALTER TABLE sounds ADD COLUMN original_extension TEXT;
+ALTER TABLE sounds ADD COLUMN original_file_hash TEXT;
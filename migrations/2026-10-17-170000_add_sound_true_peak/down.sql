@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE sounds DROP COLUMN true_peak_dbtp;
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE sounds ADD COLUMN true_peak_dbtp REAL;
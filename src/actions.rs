@@ -1,3 +1,14 @@
+pub mod archive;
+pub mod audio;
+pub mod dca;
+pub mod fingerprint;
 pub mod fs;
+pub mod ingest;
+pub mod pagination;
+pub mod remote;
+pub mod search;
+pub mod slugs;
 pub mod sounds;
 pub mod tags;
+pub mod waveform;
+pub mod zip;
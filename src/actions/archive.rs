@@ -0,0 +1,349 @@
+use std::{
+    collections::HashSet,
+    fs,
+    io::{Error, ErrorKind, Read, Write},
+    path::Path,
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use zip::ZipArchive;
+
+use crate::{
+    actions::{
+        fs::{StageError, StagedSound},
+        zip::ZipStreamWriter,
+    },
+    models::SoundWithTags,
+};
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Manifests only hold names and tags, anything
+/// bigger than this is not a valid archive.
+const MAX_MANIFEST_BYTES: u64 = 16 * 1024 * 1024;
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEntry {
+    /// Name of the audio file inside of the archive
+    pub file_name: String,
+    pub name: String,
+    pub extension: String,
+    pub file_hash: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub sounds: Vec<ManifestEntry>,
+}
+
+/// Writes a ZIP archive with the audio files of the given sounds and a
+/// `manifest.json` describing them to `writer`, front to back.
+///
+/// Audio files are named after the sound name rather than the stored file name.
+/// They are already compressed, so entries are stored as is.
+pub fn write_sounds_archive(
+    sounds: Vec<SoundWithTags>,
+    audio_folder_path: &Path,
+    writer: impl Write,
+) -> Result<(), Error> {
+    let mut archive = ZipStreamWriter::new(writer);
+    let mut manifest = Manifest { sounds: vec![] };
+    let mut used_file_names = HashSet::new();
+
+    for sound in sounds {
+        let extension = sound.extension.trim_start_matches('.').to_string();
+        let mut filepath = audio_folder_path.join(&sound.file_name);
+        filepath.set_extension(&extension);
+
+        let (mut file, size) = match fs::File::open(&filepath)
+            .and_then(|file| file.metadata().map(|metadata| (file, metadata.len())))
+        {
+            Ok(opened) => opened,
+            Err(reason) => {
+                warn!(
+                    "Skipping sound with id {} from the archive. Reason: {:?}",
+                    sound.id, reason
+                );
+                continue;
+            }
+        };
+
+        let file_name = archive_file_name(&sound.name, &extension, &used_file_names);
+        used_file_names.insert(file_name.clone());
+
+        archive.write_entry(&file_name, size, &mut file)?;
+
+        manifest.sounds.push(ManifestEntry {
+            file_name,
+            name: sound.name,
+            extension,
+            file_hash: sound.file_hash,
+            tags: sound.tags,
+        });
+    }
+
+    let manifest_content = serde_json::to_vec_pretty(&manifest)?;
+
+    archive.write_entry(
+        MANIFEST_FILE_NAME,
+        manifest_content.len() as u64,
+        &mut manifest_content.as_slice(),
+    )?;
+    archive.finish()?;
+
+    Ok(())
+}
+
+/// Builds a file name out of the sound name that is safe to use
+/// inside of an archive and that doesn't clash with previous entries.
+fn archive_file_name(name: &str, extension: &str, used_file_names: &HashSet<String>) -> String {
+    let stem = name
+        .chars()
+        .map(|character| match character {
+            '/' | '\\' | ':' => '_',
+            character if character.is_control() => '_',
+            character => character,
+        })
+        .collect::<String>();
+    let stem = stem.trim().trim_start_matches('.');
+    let stem = if stem.is_empty() { "sound" } else { stem };
+
+    let mut file_name = format!("{}.{}", stem, extension);
+    let mut attempt = 1;
+
+    while used_file_names.contains(&file_name) || file_name == MANIFEST_FILE_NAME {
+        attempt += 1;
+        file_name = format!("{} ({}).{}", stem, attempt, extension);
+    }
+
+    file_name
+}
+
+pub struct ArchiveEntry {
+    pub file_name: String,
+    /// The extracted content, unless it failed or went over the size limit
+    pub sound: Result<StagedSound, StageError>,
+}
+
+/// Opens a ZIP archive from disk along with its manifest, when it has one.
+/// Archives with more than `max_entries` entries besides the manifest are refused.
+pub fn open_sounds_archive(
+    archive_path: &Path,
+    max_entries: usize,
+) -> Result<(ZipArchive<fs::File>, Option<Manifest>), Error> {
+    let mut archive = ZipArchive::new(fs::File::open(archive_path)?)?;
+
+    if archive.len() > max_entries + 1 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Archive has more than {} files.", max_entries),
+        ));
+    }
+
+    let manifest = match archive.by_name(MANIFEST_FILE_NAME) {
+        Ok(manifest_file) => {
+            /*
+             * The declared size can't be trusted,
+             * so the content is capped while reading too.
+             */
+            if manifest_file.size() > MAX_MANIFEST_BYTES {
+                return Err(manifest_too_large());
+            }
+
+            let mut manifest_content = vec![];
+            manifest_file
+                .take(MAX_MANIFEST_BYTES + 1)
+                .read_to_end(&mut manifest_content)?;
+
+            if manifest_content.len() as u64 > MAX_MANIFEST_BYTES {
+                return Err(manifest_too_large());
+            }
+
+            Some(serde_json::from_slice::<Manifest>(&manifest_content)?)
+        }
+        Err(zip::result::ZipError::FileNotFound) => None,
+        Err(reason) => return Err(reason.into()),
+    };
+
+    Ok((archive, manifest))
+}
+
+fn manifest_too_large() -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Manifest is bigger than {} bytes.", MAX_MANIFEST_BYTES),
+    )
+}
+
+/// Extracts the entry at `index` into a staged file in the audio folder, giving up
+/// once it decompresses to more than `max_file_bytes`. Directories, the manifest
+/// and macOS resource forks are skipped by returning `None`.
+pub fn read_sounds_archive_entry(
+    archive: &mut ZipArchive<fs::File>,
+    index: usize,
+    audio_folder_path: &Path,
+    max_file_bytes: u64,
+) -> Result<Option<ArchiveEntry>, Error> {
+    let mut entry = archive.by_index(index)?;
+    let file_name = entry.name().to_string();
+
+    if entry.is_dir() || file_name == MANIFEST_FILE_NAME || file_name.starts_with("__MACOSX/") {
+        return Ok(None);
+    }
+
+    let sound = if entry.size() > max_file_bytes {
+        Err(StageError::TooLarge(max_file_bytes))
+    } else {
+        StagedSound::from_reader(&mut entry, audio_folder_path, max_file_bytes)
+    };
+
+    Ok(Some(ArchiveEntry { file_name, sound }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Read, path::PathBuf};
+
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::test_utils::TestFolder;
+
+    fn sound(name: &str, file_name: &str) -> SoundWithTags {
+        SoundWithTags {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            extension: "mp3".to_string(),
+            file_name: file_name.to_string(),
+            file_hash: format!("hash-{}", file_name),
+            created_at: 0,
+            play_count: 0,
+            playback_file_name: None,
+            duration_ms: None,
+            sample_rate: None,
+            channels: None,
+            codec: None,
+            edited: false,
+            tags: vec!["meme".to_string()],
+        }
+    }
+
+    fn write_archive(folder: &TestFolder, sounds: Vec<SoundWithTags>) -> PathBuf {
+        let archive_path = folder.0.join("sounds.zip");
+        let archive_file = fs::File::create(&archive_path).unwrap();
+
+        write_sounds_archive(sounds, &folder.0, archive_file).unwrap();
+
+        archive_path
+    }
+
+    #[test]
+    fn archive_round_trips_sounds_and_manifest() {
+        let folder = TestFolder::new();
+        fs::write(folder.0.join("first.mp3"), b"first sound").unwrap();
+        fs::write(folder.0.join("second.mp3"), b"second sound").unwrap();
+
+        let archive_path = write_archive(
+            &folder,
+            vec![
+                sound("same name", "first"),
+                sound("same name", "second"),
+                sound("missing", "missing"),
+            ],
+        );
+
+        let (mut archive, manifest) = open_sounds_archive(&archive_path, 10).unwrap();
+        let manifest = manifest.unwrap();
+        let file_names = manifest
+            .sounds
+            .iter()
+            .map(|entry| entry.file_name.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(file_names, vec!["same name.mp3", "same name (2).mp3"]);
+        assert_eq!(archive.len(), 3);
+
+        let mut contents = vec![];
+
+        for index in 0..archive.len() {
+            if let Some(entry) =
+                read_sounds_archive_entry(&mut archive, index, &folder.0, 1024).unwrap()
+            {
+                let mut content = String::new();
+                fs::File::open(entry.sound.unwrap().path())
+                    .unwrap()
+                    .read_to_string(&mut content)
+                    .unwrap();
+                contents.push((entry.file_name, content));
+            }
+        }
+
+        assert_eq!(
+            contents,
+            vec![
+                ("same name.mp3".to_string(), "first sound".to_string()),
+                ("same name (2).mp3".to_string(), "second sound".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn archive_with_more_entries_than_plain_zip_allows_round_trips() {
+        let folder = TestFolder::new();
+        fs::write(folder.0.join("sound.mp3"), b"x").unwrap();
+
+        let sound_count = usize::from(u16::MAX) + 10;
+        let sounds = (0..sound_count)
+            .map(|index| sound(&index.to_string(), "sound"))
+            .collect::<Vec<_>>();
+        let archive_path = write_archive(&folder, sounds);
+
+        let (archive, manifest) = open_sounds_archive(&archive_path, sound_count).unwrap();
+
+        assert_eq!(archive.len(), sound_count + 1);
+        assert_eq!(manifest.unwrap().sounds.len(), sound_count);
+    }
+
+    #[test]
+    fn archive_limits_are_enforced() {
+        let folder = TestFolder::new();
+        fs::write(folder.0.join("first.mp3"), b"first sound").unwrap();
+        fs::write(folder.0.join("second.mp3"), b"second sound").unwrap();
+
+        let archive_path = write_archive(
+            &folder,
+            vec![sound("first", "first"), sound("second", "second")],
+        );
+
+        assert!(open_sounds_archive(&archive_path, 1).is_err());
+
+        let (mut archive, _) = open_sounds_archive(&archive_path, 2).unwrap();
+        let entry = read_sounds_archive_entry(&mut archive, 0, &folder.0, 4)
+            .unwrap()
+            .unwrap();
+
+        assert!(matches!(entry.sound, Err(StageError::TooLarge(4))));
+    }
+
+    #[test]
+    fn archive_file_names_are_sanitized() {
+        let used_file_names = HashSet::new();
+
+        assert_eq!(
+            archive_file_name("../a/b:c", "mp3", &used_file_names),
+            "_a_b_c.mp3"
+        );
+        assert_eq!(
+            archive_file_name(" . ", "mp3", &used_file_names),
+            "sound.mp3"
+        );
+        assert_eq!(
+            archive_file_name("manifest", "json", &used_file_names),
+            "manifest (2).json"
+        );
+    }
+}
@@ -0,0 +1,492 @@
+use std::{
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::actions::dca::ogg_opus_to_dca;
+
+/// Bounds of the gain applied to reach the loudness target, so that
+/// near silent sounds aren't amplified into pure noise.
+const MIN_LOUDNESS_GAIN: f32 = 0.1;
+const MAX_LOUDNESS_GAIN: f32 = 4.0;
+
+/// Highest true peak a sound is allowed to reach once its gain is applied,
+/// leaving headroom for the Opus encoder so boosted sounds don't clip.
+const MAX_TRUE_PEAK_DBTP: f64 = -1.0;
+
+#[derive(Deserialize)]
+struct LoudnormMeasurement {
+    input_i: String,
+    input_tp: String,
+}
+
+/// Loudness measurement of an audio file.
+#[derive(Clone, Copy, Debug)]
+pub struct Loudness {
+    /// Integrated loudness (EBU R128), in LUFS
+    pub integrated_lufs: f64,
+    /// Highest true peak, in dBTP
+    pub true_peak_dbtp: f64,
+}
+
+#[derive(Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    codec_name: Option<String>,
+    sample_rate: Option<String>,
+    channels: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+}
+
+/// Section of a sound kept by an edit, with optional fades. Every value is in milliseconds.
+#[derive(Clone, Copy, Debug)]
+pub struct SoundEdit {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub fade_in_ms: i64,
+    pub fade_out_ms: i64,
+}
+
+/// Shortest stretch of audio considered silence.
+const MIN_SILENCE_SECONDS: f64 = 0.1;
+
+/// Silence removed from each end of a sound, in milliseconds.
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SilenceTrim {
+    pub leading_ms: i64,
+    pub trailing_ms: i64,
+}
+
+/// Properties of the first audio stream of a file.
+#[derive(Clone, Debug, Default)]
+pub struct AudioMetadata {
+    pub duration_ms: Option<i64>,
+    pub sample_rate: Option<i32>,
+    pub channels: Option<i32>,
+    pub codec: Option<String>,
+}
+
+/// Reads the duration of an audio file, in seconds, through `ffprobe`.
+pub async fn probe_duration(path: &Path) -> Result<f64, Error> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(path)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "Failed to read the audio duration."))
+}
+
+/// Reads the duration, sample rate, channel count and codec of an audio file through `ffprobe`.
+pub async fn probe_metadata(path: &Path) -> Result<AudioMetadata, Error> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "a:0",
+            "-show_entries",
+            "format=duration:stream=codec_name,sample_rate,channels",
+            "-of",
+            "json",
+        ])
+        .arg(path)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    let probe = serde_json::from_slice::<ProbeOutput>(&output.stdout)
+        .map_err(|reason| Error::new(ErrorKind::InvalidData, reason))?;
+    let stream = probe.streams.into_iter().next();
+    let duration_ms = probe
+        .format
+        .and_then(|format| format.duration)
+        .and_then(|duration| duration.parse::<f64>().ok())
+        .map(|duration| (duration * 1000.0).round() as i64);
+
+    Ok(match stream {
+        Some(stream) => AudioMetadata {
+            duration_ms,
+            sample_rate: stream
+                .sample_rate
+                .and_then(|sample_rate| sample_rate.parse::<i32>().ok()),
+            channels: stream.channels,
+            codec: stream.codec_name,
+        },
+        None => AudioMetadata {
+            duration_ms,
+            ..AudioMetadata::default()
+        },
+    })
+}
+
+/// Writes the trimmed and faded section of a sound to `destination`,
+/// encoded in the format matching its extension.
+pub async fn apply_sound_edit(
+    source: &Path,
+    destination: &Path,
+    edit: SoundEdit,
+) -> Result<(), Error> {
+    let seconds = |milliseconds: i64| format!("{:.3}", milliseconds as f64 / 1000.0);
+    let mut filters = vec![
+        format!(
+            "atrim=start={}:end={}",
+            seconds(edit.start_ms),
+            seconds(edit.end_ms)
+        ),
+        "asetpts=PTS-STARTPTS".to_string(),
+    ];
+
+    if edit.fade_in_ms > 0 {
+        filters.push(format!("afade=t=in:st=0:d={}", seconds(edit.fade_in_ms)));
+    }
+
+    if edit.fade_out_ms > 0 {
+        filters.push(format!(
+            "afade=t=out:st={}:d={}",
+            seconds(edit.end_ms - edit.start_ms - edit.fade_out_ms),
+            seconds(edit.fade_out_ms)
+        ));
+    }
+
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-y", "-i"])
+        .arg(source)
+        .args(["-vn", "-af", &filters.join(",")])
+        .arg(destination)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Decodes the whole audio stream of a file, failing on the
+/// first error so truncated or corrupted files are caught.
+pub async fn verify_decodes(path: &Path) -> Result<(), Error> {
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-xerror", "-i"])
+        .arg(path)
+        .args(["-vn", "-f", "null", "-"])
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        let reason = String::from_utf8_lossy(&output.stderr).trim().to_string();
+
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            if reason.is_empty() {
+                "Failed to decode the audio stream.".to_string()
+            } else {
+                reason
+            },
+        ));
+    }
+
+    Ok(())
+}
+
+/// Finds the leading and trailing silence of a sound through ffmpeg's `silencedetect` filter.
+///
+/// Returns `None` when there is nothing to trim, or when
+/// the sound is silent as a whole.
+pub async fn detect_silence(
+    path: &Path,
+    duration_ms: i64,
+    threshold_db: f64,
+) -> Result<Option<SilenceTrim>, Error> {
+    let duration = duration_ms as f64 / 1000.0;
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(path)
+        .args([
+            "-af",
+            &format!(
+                "silencedetect=noise={}dB:d={}",
+                threshold_db, MIN_SILENCE_SECONDS
+            ),
+            "-f",
+            "null",
+            "-",
+        ])
+        .output()
+        .await?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    if !output.status.success() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            stderr.trim().to_string(),
+        ));
+    }
+
+    let read_value = |line: &str, key: &str| {
+        line.split(key)
+            .nth(1)
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|value| value.parse::<f64>().ok())
+    };
+
+    // Every silence is logged as a start, followed by an end unless it lasts until the end of the file
+    let mut silences: Vec<(f64, Option<f64>)> = Vec::new();
+
+    for line in stderr.lines() {
+        if let Some(start) = read_value(line, "silence_start: ") {
+            silences.push((start, None));
+        } else if let Some(end) = read_value(line, "silence_end: ") {
+            if let Some(silence) = silences.last_mut() {
+                silence.1 = Some(end);
+            }
+        }
+    }
+
+    let tolerance = 0.01;
+    let leading = silences
+        .first()
+        .filter(|(start, _)| *start <= tolerance)
+        .map_or(0.0, |(_, end)| end.unwrap_or(duration));
+    let trailing = silences
+        .last()
+        .filter(|(_, end)| end.is_none_or(|end| end >= duration - tolerance))
+        .map_or(0.0, |(start, _)| duration - start.max(0.0));
+
+    if leading + trailing >= duration || (leading <= 0.0 && trailing <= 0.0) {
+        return Ok(None);
+    }
+
+    Ok(Some(SilenceTrim {
+        leading_ms: (leading * 1000.0).round() as i64,
+        trailing_ms: (trailing * 1000.0).round() as i64,
+    }))
+}
+
+/// Extension of the pre-encoded playback renditions.
+pub const PLAYBACK_EXTENSION: &str = "dca";
+
+const PLAYBACK_BITRATE: u64 = 128_000;
+
+/// Name of the playback rendition stored next to the original sound file.
+pub fn playback_file_name(file_name: &str) -> String {
+    format!("{}-playback", file_name)
+}
+
+/// Transcodes a sound into the DCA rendition used for voice playback, made
+/// of 20ms Opus frames in the 48kHz stereo layout Discord expects.
+///
+/// `gain` is applied while encoding, since Songbird has to decode
+/// the frames again to play them at any volume other than 1.0.
+pub async fn transcode_playback(source: &Path, destination: &Path, gain: f32) -> Result<(), Error> {
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-i"])
+        .arg(source)
+        .args([
+            "-vn",
+            "-af",
+            &format!("volume={}", gain),
+            "-c:a",
+            "libopus",
+            "-b:a",
+            &PLAYBACK_BITRATE.to_string(),
+            "-frame_duration",
+            "20",
+            "-ar",
+            "48000",
+            "-ac",
+            "2",
+            "-f",
+            "ogg",
+            "pipe:1",
+        ])
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    let dca = ogg_opus_to_dca(&output.stdout, 2, PLAYBACK_BITRATE)?;
+
+    tokio::fs::write(destination, dca).await
+}
+
+/// Formats Telegram plays as audio, others are sent as an MP3 rendition.
+const TELEGRAM_AUDIO_EXTENSIONS: [&str; 2] = ["mp3", "m4a"];
+
+/// Extension of the renditions sent to Telegram.
+pub const TELEGRAM_EXTENSION: &str = "mp3";
+
+/// Name of the Telegram rendition stored next to the original sound file.
+pub fn telegram_file_name(file_name: &str) -> String {
+    format!("{}-telegram", file_name)
+}
+
+/// Path of the MP3 rendition sent to Telegram,
+/// `None` for formats Telegram plays as they are.
+pub fn telegram_rendition_path(
+    audio_folder_path: &Path,
+    file_name: &str,
+    extension: &str,
+) -> Option<PathBuf> {
+    if TELEGRAM_AUDIO_EXTENSIONS.contains(&extension) {
+        return None;
+    }
+
+    let mut path = audio_folder_path.join(telegram_file_name(file_name));
+    path.set_extension(TELEGRAM_EXTENSION);
+    Some(path)
+}
+
+/// Transcodes a sound to MP3, the format Telegram plays inline.
+pub async fn transcode_mp3(source: &Path, destination: &Path) -> Result<(), Error> {
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-y", "-i"])
+        .arg(source)
+        .args(["-vn", "-c:a", "libmp3lame", "-q:a", "2", "-f", "mp3"])
+        .arg(destination)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Measures the integrated loudness (EBU R128) and the true peak of an
+/// audio file through the first pass of ffmpeg's `loudnorm` filter.
+pub async fn measure_loudness(path: &Path) -> Result<Loudness, Error> {
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(path)
+        .args(["-af", "loudnorm=print_format=json", "-f", "null", "-"])
+        .output()
+        .await?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    if !output.status.success() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            stderr.trim().to_string(),
+        ));
+    }
+
+    // The measurement is printed as the last JSON object of the log
+    let measurement = stderr
+        .rfind('{')
+        .and_then(|start| serde_json::from_str::<LoudnormMeasurement>(&stderr[start..]).ok())
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Failed to read the loudness."))?;
+
+    match (
+        measurement.input_i.parse::<f64>(),
+        measurement.input_tp.parse::<f64>(),
+    ) {
+        (Ok(integrated_lufs), Ok(true_peak_dbtp))
+            if integrated_lufs.is_finite() && true_peak_dbtp.is_finite() =>
+        {
+            Ok(Loudness {
+                integrated_lufs,
+                true_peak_dbtp,
+            })
+        }
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Sound has no measurable loudness: {} LUFS, {} dBTP",
+                measurement.input_i, measurement.input_tp
+            ),
+        )),
+    }
+}
+
+/// Volume multiplier that brings a sound from its measured loudness to the
+/// target, lowered when needed so its true peak stays under `MAX_TRUE_PEAK_DBTP`.
+pub fn loudness_gain(loudness_lufs: f64, true_peak_dbtp: Option<f64>, target_lufs: f64) -> f32 {
+    let gain = 10f64
+        .powf((target_lufs - loudness_lufs) / 20.0)
+        .clamp(MIN_LOUDNESS_GAIN as f64, MAX_LOUDNESS_GAIN as f64);
+
+    let gain = match true_peak_dbtp {
+        Some(true_peak_dbtp) => gain.min(10f64.powf((MAX_TRUE_PEAK_DBTP - true_peak_dbtp) / 20.0)),
+        None => gain,
+    };
+
+    gain as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loudness_gain_reaches_the_target() {
+        assert!((loudness_gain(-22.0, Some(-12.0), -16.0) - 1.995).abs() < 0.01);
+        assert!((loudness_gain(-10.0, None, -16.0) - 0.501).abs() < 0.01);
+    }
+
+    #[test]
+    fn loudness_gain_keeps_the_true_peak_under_the_limit() {
+        let gain = loudness_gain(-22.0, Some(-3.0), -16.0);
+        let true_peak_dbtp = -3.0 + 20.0 * (gain as f64).log10();
+
+        assert!((true_peak_dbtp - MAX_TRUE_PEAK_DBTP).abs() < 0.01);
+    }
+
+    #[test]
+    fn loudness_gain_is_bounded() {
+        assert_eq!(loudness_gain(-60.0, None, -16.0), MAX_LOUDNESS_GAIN);
+        assert_eq!(loudness_gain(10.0, None, -16.0), MIN_LOUDNESS_GAIN);
+    }
+}
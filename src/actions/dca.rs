@@ -0,0 +1,187 @@
+use std::{
+    convert::TryFrom,
+    io::{Error, ErrorKind},
+};
+
+use serde_json::json;
+
+const OGG_CAPTURE_PATTERN: &[u8] = b"OggS";
+const OGG_PAGE_HEADER_LENGTH: usize = 27;
+
+/// Opus frames of 20ms at 48kHz, the only size Discord takes as is.
+const DCA_FRAME_SIZE: u64 = 960;
+
+/// Splits an Ogg stream into its packets. Pages are trusted to be well formed
+/// since they come from ffmpeg, only their bounds are checked.
+fn ogg_packets(ogg: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+    let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+    let mut packets = vec![];
+    let mut packet = vec![];
+    let mut offset = 0;
+
+    while offset < ogg.len() {
+        let header = ogg
+            .get(offset..offset + OGG_PAGE_HEADER_LENGTH)
+            .ok_or_else(|| invalid("Ogg page header is truncated."))?;
+
+        if &header[..4] != OGG_CAPTURE_PATTERN {
+            return Err(invalid("Ogg page doesn't start with the capture pattern."));
+        }
+
+        let segment_count = header[26] as usize;
+        let segment_table_start = offset + OGG_PAGE_HEADER_LENGTH;
+        let segment_table = ogg
+            .get(segment_table_start..segment_table_start + segment_count)
+            .ok_or_else(|| invalid("Ogg segment table is truncated."))?;
+        let mut segment_start = segment_table_start + segment_count;
+
+        for &segment_length in segment_table {
+            let segment_end = segment_start + segment_length as usize;
+            let segment = ogg
+                .get(segment_start..segment_end)
+                .ok_or_else(|| invalid("Ogg segment is truncated."))?;
+
+            packet.extend_from_slice(segment);
+            segment_start = segment_end;
+
+            // Packets continue in the next segment as long as segments are full
+            if segment_length < 255 {
+                packets.push(std::mem::take(&mut packet));
+            }
+        }
+
+        offset = segment_start;
+    }
+
+    Ok(packets)
+}
+
+/// Rewrites an Ogg/Opus stream as a DCA1 file, the format songbird
+/// reads Opus frames from without decoding them first.
+///
+/// The `OpusHead` and `OpusTags` headers are dropped, every other packet
+/// is written as its length followed by its content.
+pub fn ogg_opus_to_dca(ogg: &[u8], channels: u8, bitrate: u64) -> Result<Vec<u8>, Error> {
+    let packets = ogg_packets(ogg)?;
+
+    match packets.first() {
+        Some(head) if head.starts_with(b"OpusHead") => {}
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Ogg stream doesn't hold Opus audio.",
+            ))
+        }
+    }
+
+    let metadata = json!({
+        "dca": {
+            "version": 1,
+            "tool": {
+                "name": env!("CARGO_PKG_NAME"),
+                "version": env!("CARGO_PKG_VERSION"),
+                "url": "",
+                "author": "",
+            },
+        },
+        "opus": {
+            "mode": "audio",
+            "sample_rate": 48000,
+            "frame_size": DCA_FRAME_SIZE,
+            "abr": bitrate,
+            "vbr": 1,
+            "channels": channels,
+        },
+    })
+    .to_string();
+
+    let mut dca = Vec::with_capacity(ogg.len());
+    dca.extend_from_slice(b"DCA1");
+    dca.extend_from_slice(&(metadata.len() as i32).to_le_bytes());
+    dca.extend_from_slice(metadata.as_bytes());
+
+    for packet in packets.iter().skip(2) {
+        let length = i16::try_from(packet.len())
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Opus packet is too large."))?;
+
+        dca.extend_from_slice(&length.to_le_bytes());
+        dca.extend_from_slice(packet);
+    }
+
+    Ok(dca)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ogg_page(packets: &[&[u8]]) -> Vec<u8> {
+        let mut segment_table = vec![];
+        let mut content = vec![];
+
+        for packet in packets {
+            let mut remaining = packet.len();
+
+            while remaining >= 255 {
+                segment_table.push(255);
+                remaining -= 255;
+            }
+
+            segment_table.push(remaining as u8);
+            content.extend_from_slice(packet);
+        }
+
+        let mut page = OGG_CAPTURE_PATTERN.to_vec();
+        page.extend_from_slice(&[0; 22]);
+        page.push(segment_table.len() as u8);
+        page.extend_from_slice(&segment_table);
+        page.extend_from_slice(&content);
+
+        page
+    }
+
+    #[test]
+    fn ogg_packets_are_joined_across_full_segments() {
+        let long_packet = vec![7u8; 600];
+        let mut ogg = ogg_page(&[b"first", &long_packet]);
+        ogg.extend(ogg_page(&[b"", b"last"]));
+
+        let packets = ogg_packets(&ogg).unwrap();
+
+        assert_eq!(
+            packets,
+            vec![b"first".to_vec(), long_packet, vec![], b"last".to_vec()]
+        );
+    }
+
+    #[test]
+    fn truncated_ogg_is_refused() {
+        let ogg = ogg_page(&[b"OpusHead", b"OpusTags", b"frame"]);
+
+        assert!(ogg_packets(&ogg[..ogg.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn dca_holds_every_audio_packet() {
+        let ogg = ogg_page(&[b"OpusHead", b"OpusTags", b"one", b"two"]);
+
+        let dca = ogg_opus_to_dca(&ogg, 2, 128_000).unwrap();
+        let metadata_length = i32::from_le_bytes([dca[4], dca[5], dca[6], dca[7]]) as usize;
+        let metadata =
+            serde_json::from_slice::<serde_json::Value>(&dca[8..8 + metadata_length]).unwrap();
+
+        assert_eq!(&dca[..4], b"DCA1");
+        assert_eq!(metadata["opus"]["channels"], 2);
+        assert_eq!(
+            &dca[8 + metadata_length..],
+            &[3, 0, b'o', b'n', b'e', 3, 0, b't', b'w', b'o']
+        );
+    }
+
+    #[test]
+    fn dca_needs_opus_audio() {
+        let ogg = ogg_page(&[b"\x01vorbis", b"frame"]);
+
+        assert!(ogg_opus_to_dca(&ogg, 2, 128_000).is_err());
+    }
+}
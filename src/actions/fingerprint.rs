@@ -0,0 +1,446 @@
+use std::{
+    f64::consts::PI,
+    io::{Error, ErrorKind},
+    ops::RangeInclusive,
+    path::Path,
+};
+
+use tokio::process::Command;
+
+/// Audio is decoded to mono at this rate, only the
+/// 300Hz-2000Hz range is used by the fingerprint.
+const SAMPLE_RATE: usize = 5512;
+/// Samples in each analysed frame, a power of two for the FFT.
+const FRAME_LENGTH: usize = 2048;
+/// Samples between the start of two consecutive frames.
+const FRAME_HOP: usize = 256;
+const MIN_FREQUENCY: f64 = 300.0;
+const MAX_FREQUENCY: f64 = 2000.0;
+/// 33 bands give the 32 energy differences of a sub-fingerprint.
+const BANDS: usize = 33;
+
+/// Highest share of differing bits for two fingerprints to be
+/// considered the same clip. Unrelated clips sit around 0.5.
+const MAX_BIT_ERROR_RATE: f64 = 0.25;
+/// Frames two fingerprints can be shifted by when aligning them,
+/// to account for encoders adding or removing padding.
+const MAX_FRAME_OFFSET: isize = 20;
+/// Shortest aligned section, relative to the longest fingerprint,
+/// for a comparison to count. Also rules out clips of different lengths.
+const MIN_OVERLAP_RATIO: f64 = 0.8;
+
+/// Computes a Haitsma-Kalker style fingerprint: one 32 bit sub-fingerprint
+/// per frame, each bit telling whether the energy difference between two
+/// neighbouring frequency bands grew or shrank since the previous frame.
+///
+/// It survives re-encoding at other bitrates or in other containers,
+/// which the file hash doesn't.
+pub async fn compute_fingerprint(path: &Path) -> Result<Vec<u32>, Error> {
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-i"])
+        .arg(path)
+        .args(["-vn", "-ac", "1", "-ar"])
+        .arg(SAMPLE_RATE.to_string())
+        .args(["-f", "s16le", "-"])
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    let samples = output
+        .stdout
+        .chunks_exact(2)
+        .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]) as f64 / i16::MAX as f64)
+        .collect::<Vec<f64>>();
+
+    Ok(fingerprint_samples(&samples))
+}
+
+fn fingerprint_samples(samples: &[f64]) -> Vec<u32> {
+    if samples.len() < FRAME_LENGTH {
+        return vec![];
+    }
+
+    let window = (0..FRAME_LENGTH)
+        .map(|index| 0.5 - 0.5 * (2.0 * PI * index as f64 / (FRAME_LENGTH - 1) as f64).cos())
+        .collect::<Vec<f64>>();
+
+    let band_edges = (0..=BANDS)
+        .map(|band| {
+            let frequency =
+                MIN_FREQUENCY * (MAX_FREQUENCY / MIN_FREQUENCY).powf(band as f64 / BANDS as f64);
+            (frequency * FRAME_LENGTH as f64 / SAMPLE_RATE as f64).round() as usize
+        })
+        .collect::<Vec<usize>>();
+
+    let mut previous_energies: Option<Vec<f64>> = None;
+    let mut fingerprint = Vec::new();
+    let mut real = vec![0.0; FRAME_LENGTH];
+    let mut imaginary = vec![0.0; FRAME_LENGTH];
+
+    for start in (0..=samples.len() - FRAME_LENGTH).step_by(FRAME_HOP) {
+        let frame = &samples[start..start + FRAME_LENGTH];
+
+        for ((value, sample), weight) in real.iter_mut().zip(frame).zip(&window) {
+            *value = sample * weight;
+        }
+        imaginary.iter_mut().for_each(|value| *value = 0.0);
+
+        fft(&mut real, &mut imaginary);
+
+        let energies = band_edges
+            .windows(2)
+            .map(|edges| {
+                (edges[0]..edges[1].max(edges[0] + 1))
+                    .map(|bin| real[bin] * real[bin] + imaginary[bin] * imaginary[bin])
+                    .sum::<f64>()
+            })
+            .collect::<Vec<f64>>();
+
+        if let Some(previous) = &previous_energies {
+            let sub_fingerprint = (0..BANDS - 1).fold(0u32, |bits, band| {
+                let difference =
+                    (energies[band] - energies[band + 1]) - (previous[band] - previous[band + 1]);
+
+                if difference > 0.0 {
+                    bits | (1 << band)
+                } else {
+                    bits
+                }
+            });
+
+            fingerprint.push(sub_fingerprint);
+        }
+
+        previous_energies = Some(energies);
+    }
+
+    fingerprint
+}
+
+/// In place iterative radix-2 FFT, `real.len()` has to be a power of two.
+fn fft(real: &mut [f64], imaginary: &mut [f64]) {
+    let length = real.len();
+    let mut target = 0;
+
+    for index in 0..length {
+        if index < target {
+            real.swap(index, target);
+            imaginary.swap(index, target);
+        }
+
+        let mut bit = length >> 1;
+        while target & bit != 0 {
+            target ^= bit;
+            bit >>= 1;
+        }
+        target |= bit;
+    }
+
+    let mut size = 2;
+    while size <= length {
+        let angle = -2.0 * PI / size as f64;
+
+        for start in (0..length).step_by(size) {
+            for offset in 0..size / 2 {
+                let (sin, cos) = (angle * offset as f64).sin_cos();
+                let even = start + offset;
+                let odd = even + size / 2;
+
+                let odd_real = real[odd] * cos - imaginary[odd] * sin;
+                let odd_imaginary = real[odd] * sin + imaginary[odd] * cos;
+
+                real[odd] = real[even] - odd_real;
+                imaginary[odd] = imaginary[even] - odd_imaginary;
+                real[even] += odd_real;
+                imaginary[even] += odd_imaginary;
+            }
+        }
+
+        size <<= 1;
+    }
+}
+
+/// Fingerprints are stored as little endian `u32`s.
+pub fn encode_fingerprint(fingerprint: &[u32]) -> Vec<u8> {
+    fingerprint
+        .iter()
+        .flat_map(|sub_fingerprint| sub_fingerprint.to_le_bytes())
+        .collect()
+}
+
+pub fn decode_fingerprint(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .chunks_exact(4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect()
+}
+
+/// Lowest share of differing bits between two fingerprints across every
+/// alignment, or `None` when they are too different in length to compare.
+fn bit_error_rate(first: &[u32], second: &[u32]) -> Option<f64> {
+    let longest = first.len().max(second.len());
+    let min_overlap = (longest as f64 * MIN_OVERLAP_RATIO).ceil() as usize;
+
+    if first.is_empty() || second.is_empty() || first.len().min(second.len()) < min_overlap {
+        return None;
+    }
+
+    (-MAX_FRAME_OFFSET..=MAX_FRAME_OFFSET)
+        .filter_map(|offset| {
+            let first_start = offset.max(0) as usize;
+            let second_start = (-offset).max(0) as usize;
+
+            if first_start >= first.len() || second_start >= second.len() {
+                return None;
+            }
+
+            let overlap = (first.len() - first_start).min(second.len() - second_start);
+
+            if overlap < min_overlap {
+                return None;
+            }
+
+            let errors = first[first_start..first_start + overlap]
+                .iter()
+                .zip(&second[second_start..second_start + overlap])
+                .map(|(first, second)| (first ^ second).count_ones() as usize)
+                .sum::<usize>();
+
+            Some(errors as f64 / (overlap * 32) as f64)
+        })
+        .min_by(|first, second| first.total_cmp(second))
+}
+
+/// Lengths a fingerprint of `length` sub-fingerprints can be compared to.
+/// Bounds are rounded outwards, `bit_error_rate` still checks the overlap.
+pub fn comparable_lengths(length: usize) -> RangeInclusive<usize> {
+    let shortest = (length as f64 * MIN_OVERLAP_RATIO).floor() as usize;
+    let longest = (length as f64 / MIN_OVERLAP_RATIO).ceil() as usize;
+
+    shortest..=longest
+}
+
+pub fn is_near_duplicate(first: &[u32], second: &[u32]) -> bool {
+    bit_error_rate(first, second).is_some_and(|rate| rate <= MAX_BIT_ERROR_RATE)
+}
+
+/// Finds the id of a sound whose fingerprint matches the given one.
+pub fn find_near_duplicate(fingerprint: &[u32], sounds: &[(String, Vec<u32>)]) -> Option<String> {
+    sounds
+        .iter()
+        .filter_map(|(sound_id, other)| {
+            bit_error_rate(fingerprint, other).map(|rate| (sound_id, rate))
+        })
+        .filter(|(_, rate)| *rate <= MAX_BIT_ERROR_RATE)
+        .min_by(|(_, first), (_, second)| first.total_cmp(second))
+        .map(|(sound_id, _)| sound_id.clone())
+}
+
+/// Finds the representative of the cluster a sound belongs to.
+fn root(parents: &mut [usize], mut index: usize) -> usize {
+    while parents[index] != index {
+        parents[index] = parents[parents[index]];
+        index = parents[index];
+    }
+
+    index
+}
+
+/// Groups sounds into clusters of near duplicates,
+/// leaving out sounds without any duplicate.
+pub fn duplicate_clusters(mut sounds: Vec<(String, Vec<u32>)>) -> Vec<Vec<String>> {
+    /*
+     * Only fingerprints of similar lengths can match,
+     * so sorting by length keeps comparisons local.
+     */
+    sounds.sort_by_key(|(_, fingerprint)| fingerprint.len());
+
+    let mut parents = (0..sounds.len()).collect::<Vec<usize>>();
+
+    for first in 0..sounds.len() {
+        for second in first + 1..sounds.len() {
+            let (first_length, second_length) = (sounds[first].1.len(), sounds[second].1.len());
+
+            if (first_length as f64) < second_length as f64 * MIN_OVERLAP_RATIO {
+                break;
+            }
+
+            if is_near_duplicate(&sounds[first].1, &sounds[second].1) {
+                let (first_root, second_root) =
+                    (root(&mut parents, first), root(&mut parents, second));
+                parents[second_root] = first_root;
+            }
+        }
+    }
+
+    let mut clusters: Vec<(usize, Vec<String>)> = Vec::new();
+
+    for (index, (sound_id, _)) in sounds.iter().enumerate() {
+        let cluster_root = root(&mut parents, index);
+        let sound_id = sound_id.clone();
+
+        match clusters.iter_mut().find(|(root, _)| *root == cluster_root) {
+            Some((_, sound_ids)) => sound_ids.push(sound_id),
+            None => clusters.push((cluster_root, vec![sound_id])),
+        }
+    }
+
+    clusters
+        .into_iter()
+        .map(|(_, sound_ids)| sound_ids)
+        .filter(|sound_ids| sound_ids.len() > 1)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic white noise in [-1, 1], standing in for decoded audio.
+    fn noise(seed: u64, length: usize) -> Vec<f64> {
+        let mut state = seed;
+
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state % 20_001) as f64 / 10_000.0 - 1.0
+            })
+            .collect()
+    }
+
+    fn rate(first: &[f64], second: &[f64]) -> f64 {
+        bit_error_rate(&fingerprint_samples(first), &fingerprint_samples(second)).unwrap()
+    }
+
+    #[test]
+    fn fft_finds_the_frequency_of_a_cosine() {
+        let length = 16;
+        let mut real = (0..length)
+            .map(|index| (2.0 * PI * 3.0 * index as f64 / length as f64).cos())
+            .collect::<Vec<f64>>();
+        let mut imaginary = vec![0.0; length];
+
+        fft(&mut real, &mut imaginary);
+
+        for bin in 0..length {
+            let magnitude = real[bin].hypot(imaginary[bin]);
+            let expected = if bin == 3 || bin == length - 3 {
+                length as f64 / 2.0
+            } else {
+                0.0
+            };
+
+            assert!((magnitude - expected).abs() < 1e-9, "bin {}", bin);
+        }
+    }
+
+    #[test]
+    fn fft_of_an_impulse_is_flat() {
+        let mut real = vec![0.0; 8];
+        real[0] = 1.0;
+        let mut imaginary = vec![0.0; 8];
+
+        fft(&mut real, &mut imaginary);
+
+        assert!(real.iter().all(|value| (value - 1.0).abs() < 1e-12));
+        assert!(imaginary.iter().all(|value| value.abs() < 1e-12));
+    }
+
+    #[test]
+    fn identical_audio_has_no_differing_bits() {
+        let samples = noise(1, SAMPLE_RATE * 3);
+
+        assert_eq!(rate(&samples, &samples), 0.0);
+    }
+
+    #[test]
+    fn reencoded_audio_stays_under_the_threshold() {
+        let samples = noise(1, SAMPLE_RATE * 3);
+
+        // Quieter, quantized to 8 bits, with a little noise and some padding added in front
+        let hiss = noise(2, samples.len());
+        let mut reencoded = vec![0.0; 300];
+        reencoded.extend(
+            samples
+                .iter()
+                .zip(&hiss)
+                .map(|(sample, hiss)| (sample * 0.5 * 127.0).round() / 127.0 + hiss * 0.1),
+        );
+
+        assert!(rate(&samples, &reencoded) <= MAX_BIT_ERROR_RATE);
+        assert!(is_near_duplicate(
+            &fingerprint_samples(&samples),
+            &fingerprint_samples(&reencoded)
+        ));
+    }
+
+    #[test]
+    fn unrelated_audio_differs_by_about_half_the_bits() {
+        let rate = rate(&noise(1, SAMPLE_RATE * 3), &noise(3, SAMPLE_RATE * 3));
+
+        assert!((0.4..=0.6).contains(&rate), "rate {}", rate);
+    }
+
+    #[test]
+    fn fingerprints_of_different_lengths_are_not_compared() {
+        let fingerprint = fingerprint_samples(&noise(1, SAMPLE_RATE * 3));
+
+        assert_eq!(
+            bit_error_rate(&fingerprint, &fingerprint[..fingerprint.len() / 2]),
+            None
+        );
+        assert!(!comparable_lengths(fingerprint.len()).contains(&(fingerprint.len() / 2)));
+    }
+
+    #[test]
+    fn duplicates_are_grouped_in_clusters() {
+        let fingerprint = |seed| {
+            noise(seed, 100)
+                .into_iter()
+                .map(|value| (value.to_bits() >> 16) as u32)
+                .collect::<Vec<u32>>()
+        };
+        let with_flipped_bits = |fingerprint: &[u32]| {
+            fingerprint
+                .iter()
+                .enumerate()
+                .map(|(index, bits)| if index % 4 == 0 { bits ^ 0b101 } else { *bits })
+                .collect::<Vec<u32>>()
+        };
+
+        let (first, second) = (fingerprint(1), fingerprint(2));
+        let sounds = vec![
+            ("first".to_string(), first.clone()),
+            ("second".to_string(), second.clone()),
+            ("unrelated".to_string(), fingerprint(3)),
+            ("first copy".to_string(), with_flipped_bits(&first)),
+            ("second copy".to_string(), with_flipped_bits(&second)),
+            ("first cut".to_string(), first[..40].to_vec()),
+        ];
+
+        let mut clusters = duplicate_clusters(sounds)
+            .into_iter()
+            .map(|mut cluster| {
+                cluster.sort();
+                cluster
+            })
+            .collect::<Vec<_>>();
+        clusters.sort();
+
+        assert_eq!(
+            clusters,
+            vec![
+                vec!["first".to_string(), "first copy".to_string()],
+                vec!["second".to_string(), "second copy".to_string()],
+            ]
+        );
+    }
+}
@@ -1,57 +1,295 @@
 use std::{
-    fs::File,
-    io::{Cursor, Error, ErrorKind, Write},
-    path::Path,
+    fmt, fs,
+    io::{Error, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
 };
 
-use actix_web::web::{self, Bytes};
-use sha256::digest_bytes;
+use actix_web::web;
+use sha2::{Digest, Sha256};
+use tokio::{fs::File, io::AsyncWriteExt};
+use uuid::Uuid;
 
-pub async fn save_sound_as_file(
-    memory_file: Cursor<Vec<u8>>,
+use crate::actions::audio::{probe_metadata, AudioMetadata};
+
+/// Amount of bytes kept from the start of an upload
+/// to identify its file type.
+const FILE_TYPE_HEADER_LENGTH: usize = 64;
+
+/// Removes the file when dropped, unless it was moved into place.
+pub struct TemporaryPath {
+    path: PathBuf,
+    persisted: bool,
+}
+
+impl TemporaryPath {
+    pub fn new(audio_folder_path: &Path) -> Self {
+        Self {
+            path: audio_folder_path.join(format!(".upload-{}.part", Uuid::new_v4())),
+            persisted: false,
+        }
+    }
+
+    /// Temporary path ending in `extension`, for tools
+    /// that pick the output format from the file name.
+    pub fn with_extension(audio_folder_path: &Path, extension: &str) -> Self {
+        let mut temporary_path = Self::new(audio_folder_path);
+        temporary_path.path.set_extension(extension);
+        temporary_path
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TemporaryPath {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Why staging an upload stopped before the end of its content.
+#[derive(Debug)]
+pub enum StageError {
+    /// Content is bigger than the given limit of bytes
+    TooLarge(u64),
+    Io(Error),
+}
+
+impl fmt::Display for StageError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StageError::TooLarge(max_file_bytes) => write!(
+                formatter,
+                "File is bigger than the limit of {} bytes.",
+                max_file_bytes
+            ),
+            StageError::Io(reason) => write!(formatter, "{}", reason),
+        }
+    }
+}
+
+impl From<Error> for StageError {
+    fn from(reason: Error) -> Self {
+        StageError::Io(reason)
+    }
+}
+
+/// Hashes and keeps the first bytes of
+/// an upload as it gets written to disk.
+struct UploadDigest {
+    hasher: Sha256,
+    header: Vec<u8>,
+    size: u64,
+}
+
+impl UploadDigest {
+    fn new() -> Self {
+        Self {
+            hasher: Sha256::new(),
+            header: Vec::with_capacity(FILE_TYPE_HEADER_LENGTH),
+            size: 0,
+        }
+    }
+
+    fn update(&mut self, chunk: &[u8]) {
+        let missing_header = FILE_TYPE_HEADER_LENGTH - self.header.len();
+        self.header
+            .extend_from_slice(&chunk[..missing_header.min(chunk.len())]);
+        self.hasher.update(chunk);
+        self.size += chunk.len() as u64;
+    }
+
+    fn file_hash(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+
+    fn finish(self, temporary_path: TemporaryPath) -> StagedSound {
+        StagedSound {
+            temporary_path,
+            header: self.header,
+            file_hash: format!("{:x}", self.hasher.finalize()),
+        }
+    }
+}
+
+/// Reads `reader` to its end into `digest`, handing every chunk to `write`
+/// once hashed. Stops as soon as the content goes over `max_file_bytes`.
+fn digest_content(
+    reader: &mut impl Read,
+    digest: &mut UploadDigest,
+    max_file_bytes: Option<u64>,
+    mut write: impl FnMut(&[u8]) -> Result<(), Error>,
+) -> Result<(), StageError> {
+    let mut buffer = vec![0u8; 64 * 1024];
+
+    loop {
+        let read = reader.read(&mut buffer)?;
+
+        if read == 0 {
+            return Ok(());
+        }
+
+        if let Some(max_file_bytes) = max_file_bytes {
+            if digest.size + read as u64 > max_file_bytes {
+                return Err(StageError::TooLarge(max_file_bytes));
+            }
+        }
+
+        digest.update(&buffer[..read]);
+        write(&buffer[..read])?;
+    }
+}
+
+/// Digest of a whole file already on disk.
+fn digest_file(path: &Path) -> Result<UploadDigest, Error> {
+    let mut digest = UploadDigest::new();
+
+    match digest_content(&mut fs::File::open(path)?, &mut digest, None, |_| Ok(())) {
+        Ok(()) => Ok(digest),
+        Err(StageError::Io(reason)) => Err(reason),
+        Err(StageError::TooLarge(_)) => unreachable!("Content has no size limit"),
+    }
+}
+
+/// Streams an upload into a temporary file inside of the audio folder,
+/// refusing to write more than `max_file_bytes`.
+pub struct StagedSoundWriter {
+    temporary_path: TemporaryPath,
+    file: File,
+    digest: UploadDigest,
+    max_file_bytes: u64,
+}
+
+impl StagedSoundWriter {
+    pub async fn create(audio_folder_path: &Path, max_file_bytes: u64) -> Result<Self, Error> {
+        let temporary_path = TemporaryPath::new(audio_folder_path);
+        let file = File::create(&temporary_path.path).await?;
+
+        Ok(Self {
+            temporary_path,
+            file,
+            digest: UploadDigest::new(),
+            max_file_bytes,
+        })
+    }
+
+    pub async fn write_chunk(&mut self, chunk: &[u8]) -> Result<(), StageError> {
+        if self.digest.size + chunk.len() as u64 > self.max_file_bytes {
+            return Err(StageError::TooLarge(self.max_file_bytes));
+        }
+
+        self.digest.update(chunk);
+        self.file.write_all(chunk).await?;
+
+        Ok(())
+    }
+
+    pub async fn finish(mut self) -> Result<StagedSound, Error> {
+        self.file.flush().await?;
+
+        Ok(self.digest.finish(self.temporary_path))
+    }
+}
+
+/// An upload fully written to a temporary file, waiting to be validated
+/// and moved into place. The temporary file is removed when it's dropped.
+pub struct StagedSound {
+    temporary_path: TemporaryPath,
+    pub header: Vec<u8>,
+    pub file_hash: String,
+}
+
+impl StagedSound {
+    /// Blocking counterpart of `StagedSoundWriter`, for content
+    /// that is only available through a synchronous reader.
+    /// Reading stops as soon as it goes over `max_file_bytes`.
+    pub fn from_reader(
+        reader: &mut impl Read,
+        audio_folder_path: &Path,
+        max_file_bytes: u64,
+    ) -> Result<Self, StageError> {
+        let temporary_path = TemporaryPath::new(audio_folder_path);
+        let mut file = fs::File::create(&temporary_path.path)?;
+        let mut digest = UploadDigest::new();
+
+        digest_content(reader, &mut digest, Some(max_file_bytes), |chunk| {
+            file.write_all(chunk)
+        })?;
+        file.flush()?;
+
+        Ok(digest.finish(temporary_path))
+    }
+
+    /// Stages a file that was written to a temporary path by another tool,
+    /// hashing it where it is instead of copying it.
+    pub fn from_file(temporary_path: TemporaryPath) -> Result<Self, Error> {
+        Ok(digest_file(&temporary_path.path)?.finish(temporary_path))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.temporary_path.path
+    }
+
+    /// Moves the staged file to `{file_name}.{extension}` in the audio folder.
+    pub async fn persist(
+        mut self,
+        file_name: &str,
+        extension: &str,
+        audio_folder_path: &Path,
+    ) -> Result<PathBuf, Error> {
+        let mut filepath = audio_folder_path.join(file_name);
+        filepath.set_extension(extension);
+
+        tokio::fs::rename(&self.temporary_path.path, &filepath).await?;
+        self.temporary_path.persisted = true;
+
+        Ok(filepath)
+    }
+}
+
+/// Hashes a file already stored on disk, the same way uploads are hashed.
+pub fn hash_file(path: &Path) -> Result<String, Error> {
+    Ok(digest_file(path)?.file_hash())
+}
+
+pub async fn remove_sound_file(
     file_name: String,
     extension: String,
     audio_folder_path: &Path,
-) -> Result<File, Error> {
+) -> Result<(), Error> {
     let mut filepath = audio_folder_path.join(&file_name);
     filepath.set_extension(extension);
 
-    /*
-     * Load buffer from memory file into the
-     * actual filesystem file
-     */
-    let file = web::block(move || {
-        let mut file = File::create(filepath).expect("File to be created");
-        file.write_all(memory_file.get_ref())
-            .expect("File content to be written to filesystem");
-        file
-    })
-    .await
-    .expect("File content to be written to filesystem");
-
-    Ok(file)
-}
-
-pub async fn validate_sound(
-    file_content: Vec<Bytes>,
-) -> Result<(Cursor<Vec<u8>>, infer::Type, String), Error> {
-    /*
-     * Creates a Cursor to load the buffer
-     * in memory before actually writing it
-     * to the disk
-     */
-    let mut memory_file = Cursor::new(Vec::<u8>::new());
-    let mut content_iter = file_content.iter().cloned();
-
-    for chunk in content_iter.by_ref() {
-        memory_file = web::block(move || memory_file.write_all(&chunk).map(|_| memory_file))
-            .await
-            .expect("File content to be written")?;
-    }
-
-    let memory_file_buf = memory_file.get_ref();
-    let file_type_slice = &memory_file_buf[0..12];
-    let file_type = match infer::get(file_type_slice) {
+    let result = web::block(move || fs::remove_file(filepath))
+        .await
+        .map_err(|reason| Error::other(reason.to_string()))?;
+
+    match result {
+        Err(reason) if reason.kind() != ErrorKind::NotFound => Err(reason),
+        _ => Ok(()),
+    }
+}
+
+/// Extensions of the accepted audio formats.
+const VALID_EXTENSIONS: [&str; 8] = ["mp3", "wav", "ogg", "webm", "flac", "m4a", "aac", "opus"];
+
+/// `infer` reports every Ogg file as `ogg`, Opus streams are
+/// told apart by the `OpusHead` packet in the first page.
+fn is_ogg_opus(header: &[u8]) -> bool {
+    header.windows(8).any(|window| window == b"OpusHead")
+}
+
+/// Identifies the format of a staged sound from its first bytes, and
+/// makes sure it really holds an audio stream ffmpeg is able to read.
+/// Files that aren't valid sounds fail with `ErrorKind::InvalidData`.
+///
+/// Returns the extension along with the metadata read while
+/// validating, so callers don't have to probe the file again.
+pub async fn validate_sound(sound: &StagedSound) -> Result<(&'static str, AudioMetadata), Error> {
+    let file_type = match infer::get(&sound.header) {
         Some(file_type) => file_type,
         None => {
             return Err(Error::new(
@@ -61,17 +299,72 @@ pub async fn validate_sound(
         }
     };
 
-    let file_extension = file_type.extension();
-    let valid_extensions = ["mp3", "wav", "ogg", "webm"];
+    let file_extension = match file_type.extension() {
+        "ogg" if is_ogg_opus(&sound.header) => "opus",
+        // Phones record audio in MP4 containers that aren't always branded as M4A
+        "mp4" => "m4a",
+        extension => extension,
+    };
 
-    if !valid_extensions.contains(&file_extension) {
+    if !VALID_EXTENSIONS.contains(&file_extension) {
         return Err(Error::new(ErrorKind::InvalidData, "File type is not valid"));
     }
 
-    /*
-     * Create a SHA256 hash from the buffer
-     */
-    let file_hash = digest_bytes(memory_file_buf);
+    // Failing to run ffprobe at all isn't a problem with the file
+    let metadata = probe_metadata(sound.path())
+        .await
+        .map_err(|reason| match reason.kind() {
+            ErrorKind::InvalidData => Error::new(
+                ErrorKind::InvalidData,
+                format!("File can't be decoded: {}", reason),
+            ),
+            _ => reason,
+        })?;
 
-    Ok((memory_file, file_type, file_hash))
+    if metadata.codec.is_none() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "File doesn't contain an audio stream.",
+        ));
+    }
+
+    Ok((file_extension, metadata))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestFolder;
+
+    #[test]
+    fn staged_and_stored_files_hash_the_same() {
+        let folder = TestFolder::new();
+        // Spans several reads
+        let content = (0..200_000).map(|index| index as u8).collect::<Vec<u8>>();
+
+        let staged =
+            StagedSound::from_reader(&mut content.as_slice(), &folder.0, content.len() as u64)
+                .unwrap();
+        let temporary_path = TemporaryPath::new(&folder.0);
+        fs::write(temporary_path.path(), &content).unwrap();
+        let from_file = StagedSound::from_file(temporary_path).unwrap();
+
+        assert_eq!(fs::read(staged.path()).unwrap(), content);
+        assert_eq!(staged.header, content[..FILE_TYPE_HEADER_LENGTH].to_vec());
+        assert_eq!(from_file.header, staged.header);
+        assert_eq!(from_file.file_hash, staged.file_hash);
+        assert_eq!(hash_file(staged.path()).unwrap(), staged.file_hash);
+        assert_eq!(staged.file_hash, format!("{:x}", Sha256::digest(&content)));
+    }
+
+    #[test]
+    fn readers_over_the_limit_are_refused() {
+        let folder = TestFolder::new();
+        let content = vec![0u8; 100];
+
+        let result = StagedSound::from_reader(&mut content.as_slice(), &folder.0, 99);
+
+        assert!(matches!(result, Err(StageError::TooLarge(99))));
+        assert_eq!(fs::read_dir(&folder.0).unwrap().count(), 0);
+    }
 }
@@ -0,0 +1,160 @@
+use std::{io::Error, path::Path};
+
+use log::error;
+
+use crate::actions::{
+    audio::{
+        loudness_gain, measure_loudness, playback_file_name, probe_metadata, telegram_file_name,
+        telegram_rendition_path, transcode_mp3, transcode_playback, AudioMetadata,
+        PLAYBACK_EXTENSION, TELEGRAM_EXTENSION,
+    },
+    fingerprint::{compute_fingerprint, encode_fingerprint},
+    fs::remove_sound_file,
+    waveform::{generate_waveform, remove_waveform, waveform_path},
+};
+
+/// Renditions and measurements derived from the audio file of a sound.
+pub struct ProcessedSound {
+    pub playback_file_name: Option<String>,
+    pub loudness_lufs: Option<f64>,
+    pub true_peak_dbtp: Option<f64>,
+    pub metadata: AudioMetadata,
+}
+
+/// Runs every ingest step on an audio file stored in the audio folder.
+///
+/// `metadata` is only probed when it isn't given, for
+/// callers that already read it while validating the file.
+/// The playback rendition is brought to `loudness_target_lufs`.
+///
+/// Failures are only logged, since a sound can still be played
+/// without any of them: voice playback falls back to the original file
+/// and Telegram plays transcode the rendition when it's missing.
+pub async fn process_sound_file(
+    audio_path: &Path,
+    file_name: &str,
+    file_hash: &str,
+    audio_folder_path: &Path,
+    metadata: Option<AudioMetadata>,
+    loudness_target_lufs: f64,
+) -> ProcessedSound {
+    let loudness = match measure_loudness(audio_path).await {
+        Ok(loudness) => Some(loudness),
+        Err(reason) => {
+            error!(
+                "Failed to measure loudness of {:?}. Reason: {:?}",
+                audio_path, reason
+            );
+            None
+        }
+    };
+
+    let playback_file_name = playback_file_name(file_name);
+    let mut playback_path = audio_folder_path.join(&playback_file_name);
+    playback_path.set_extension(PLAYBACK_EXTENSION);
+
+    let gain = loudness.map_or(1.0, |loudness| {
+        loudness_gain(
+            loudness.integrated_lufs,
+            Some(loudness.true_peak_dbtp),
+            loudness_target_lufs,
+        )
+    });
+    let playback_file_name = match transcode_playback(audio_path, &playback_path, gain).await {
+        Ok(()) => Some(playback_file_name),
+        Err(reason) => {
+            error!(
+                "Failed to transcode playback rendition for {:?}. Reason: {:?}",
+                audio_path, reason
+            );
+            let _ = tokio::fs::remove_file(&playback_path).await;
+            None
+        }
+    };
+
+    let extension = audio_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default();
+    if let Some(telegram_path) = telegram_rendition_path(audio_folder_path, file_name, extension) {
+        if let Err(reason) = transcode_mp3(audio_path, &telegram_path).await {
+            error!(
+                "Failed to transcode telegram rendition for {:?}. Reason: {:?}",
+                audio_path, reason
+            );
+            let _ = tokio::fs::remove_file(&telegram_path).await;
+        }
+    }
+
+    let waveform_path = waveform_path(audio_folder_path, file_name);
+    if let Err(reason) = generate_waveform(audio_path, file_hash, &waveform_path).await {
+        error!(
+            "Failed to generate waveform of {:?}. Reason: {:?}",
+            audio_path, reason
+        );
+    }
+
+    let metadata = match metadata {
+        Some(metadata) => metadata,
+        None => match probe_metadata(audio_path).await {
+            Ok(metadata) => metadata,
+            Err(reason) => {
+                error!(
+                    "Failed to read metadata of {:?}. Reason: {:?}",
+                    audio_path, reason
+                );
+                AudioMetadata::default()
+            }
+        },
+    };
+
+    ProcessedSound {
+        playback_file_name,
+        loudness_lufs: loudness.map(|loudness| loudness.integrated_lufs),
+        true_peak_dbtp: loudness.map(|loudness| loudness.true_peak_dbtp),
+        metadata,
+    }
+}
+
+/// Encoded fingerprint of an audio file, `None` when it can't be computed.
+///
+/// Kept out of `process_sound_file` since uploads need the
+/// fingerprint before the file is stored, to look for duplicates.
+pub async fn fingerprint_sound_file(audio_path: &Path) -> Option<Vec<u8>> {
+    match compute_fingerprint(audio_path).await {
+        Ok(fingerprint) => Some(encode_fingerprint(&fingerprint)),
+        Err(reason) => {
+            error!(
+                "Failed to fingerprint {:?}. Reason: {:?}",
+                audio_path, reason
+            );
+            None
+        }
+    }
+}
+
+/// Removes the renditions and the waveform derived from an audio file.
+pub async fn remove_derived_files(
+    file_name: &str,
+    playback_file_name: Option<String>,
+    audio_folder_path: &Path,
+) -> Result<(), Error> {
+    remove_waveform(audio_folder_path, file_name).await?;
+    remove_sound_file(
+        telegram_file_name(file_name),
+        TELEGRAM_EXTENSION.to_string(),
+        audio_folder_path,
+    )
+    .await?;
+
+    if let Some(playback_file_name) = playback_file_name {
+        remove_sound_file(
+            playback_file_name,
+            PLAYBACK_EXTENSION.to_string(),
+            audio_folder_path,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,660 @@
+use diesel::{dsl::sql, expression::SqlLiteral, prelude::*, sql_types::BigInt, sqlite::Sqlite};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::{
+        slugs::normalize_slugs,
+        sounds::{into_sound_with_tags, sound_columns},
+    },
+    models::{Sound, SoundWithTags, SoundsPage, Tag},
+    schema::{sounds, tags},
+};
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+/// `created_at` of sounds stored before upload dates were tracked.
+pub const UNKNOWN_CREATED_AT: i64 = 0;
+
+/// `created_at` with unknown upload dates sorted after every known one
+/// in ascending order. They already come last in descending order.
+fn created_at_unknown_last() -> SqlLiteral<BigInt> {
+    sql::<BigInt>(
+        "(CASE WHEN sounds.created_at = 0 THEN 9223372036854775807 ELSE sounds.created_at END)",
+    )
+}
+
+/// Escapes the `LIKE` wildcards of a search, to be used with `ESCAPE '\'`.
+fn escape_like(search: &str) -> String {
+    search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SoundsSort {
+    Name,
+    CreatedAt,
+    PlayCount,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum TagMatch {
+    Any,
+    All,
+}
+
+/// Query parameters accepted by `GET /sounds`.
+///
+/// `tags` is a comma separated list of slugs.
+#[derive(Deserialize, Clone, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SoundsFilter {
+    pub q: Option<String>,
+    pub tags: Option<String>,
+    pub tag_match: Option<TagMatch>,
+    pub sort: Option<SoundsSort>,
+    pub order: Option<SortOrder>,
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+impl SoundsFilter {
+    pub fn sort(&self) -> SoundsSort {
+        self.sort.unwrap_or(SoundsSort::Name)
+    }
+
+    pub fn order(&self) -> SortOrder {
+        match (self.order, self.sort()) {
+            (Some(order), _) => order,
+            (None, SoundsSort::Name) => SortOrder::Asc,
+            (None, _) => SortOrder::Desc,
+        }
+    }
+
+    pub fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    pub fn slugs(&self) -> Vec<String> {
+        let tags = self
+            .tags
+            .as_ref()
+            .map(|tags| tags.split(',').map(String::from).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        normalize_slugs(tags)
+    }
+
+    /// Decodes the cursor, refusing malformed ones and the
+    /// ones created for another sort or order than the filter's.
+    pub fn cursor(&self) -> Result<Option<SoundsCursor>, InvalidCursor> {
+        match &self.cursor {
+            Some(cursor) => match SoundsCursor::decode(cursor) {
+                Some(cursor) if cursor.sort() == self.sort() && cursor.order() == self.order() => {
+                    Ok(Some(cursor))
+                }
+                _ => Err(InvalidCursor),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+/// Cursor that can't be used with the filter it was given with.
+#[derive(Debug, PartialEq)]
+pub struct InvalidCursor;
+
+/// Position of the last sound of a page, along with the sort
+/// and order it was created for. It's handed to clients as an
+/// opaque hex string and decoded back on the next request.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "sort", rename_all = "camelCase")]
+pub enum SoundsCursor {
+    Name {
+        value: String,
+        id: String,
+        order: SortOrder,
+    },
+    CreatedAt {
+        value: i64,
+        id: String,
+        order: SortOrder,
+    },
+    PlayCount {
+        value: i32,
+        id: String,
+        order: SortOrder,
+    },
+}
+
+impl SoundsCursor {
+    fn after(sound: &Sound, sort: SoundsSort, order: SortOrder) -> Self {
+        let id = sound.id.clone();
+
+        match sort {
+            SoundsSort::Name => SoundsCursor::Name {
+                value: sound.name.clone(),
+                id,
+                order,
+            },
+            SoundsSort::CreatedAt => SoundsCursor::CreatedAt {
+                value: match order {
+                    SortOrder::Asc if sound.created_at == UNKNOWN_CREATED_AT => i64::MAX,
+                    _ => sound.created_at,
+                },
+                id,
+                order,
+            },
+            SoundsSort::PlayCount => SoundsCursor::PlayCount {
+                value: sound.play_count,
+                id,
+                order,
+            },
+        }
+    }
+
+    pub fn sort(&self) -> SoundsSort {
+        match self {
+            SoundsCursor::Name { .. } => SoundsSort::Name,
+            SoundsCursor::CreatedAt { .. } => SoundsSort::CreatedAt,
+            SoundsCursor::PlayCount { .. } => SoundsSort::PlayCount,
+        }
+    }
+
+    pub fn order(&self) -> SortOrder {
+        match self {
+            SoundsCursor::Name { order, .. }
+            | SoundsCursor::CreatedAt { order, .. }
+            | SoundsCursor::PlayCount { order, .. } => *order,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        serde_json::to_vec(self)
+            .expect("Cursor to be serializable")
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let bytes = (0..cursor.len())
+            .step_by(2)
+            .map(|index| {
+                cursor
+                    .get(index..index + 2)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()?;
+
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+/// Applies the keyset condition for the given cursor and orders the
+/// query by `$column`, using the sound id to break ties.
+macro_rules! sort_by {
+    ($query:ident, $column:expr, $after:expr, $order:expr) => {
+        match $order {
+            SortOrder::Asc => {
+                if let Some((value, id)) = $after {
+                    $query = $query.filter(
+                        $column
+                            .gt(value.clone())
+                            .or($column.eq(value).and(sounds::id.gt(id))),
+                    );
+                }
+
+                $query.order(($column.asc(), sounds::id.asc()))
+            }
+            SortOrder::Desc => {
+                if let Some((value, id)) = $after {
+                    $query = $query.filter(
+                        $column
+                            .lt(value.clone())
+                            .or($column.eq(value).and(sounds::id.lt(id))),
+                    );
+                }
+
+                $query.order(($column.desc(), sounds::id.desc()))
+            }
+        }
+    };
+}
+
+/// Builds the query matching the name search and tag filters,
+/// without any ordering or pagination applied.
+pub fn filtered_sounds_query(filter: &SoundsFilter) -> sounds::BoxedQuery<'static, Sqlite> {
+    let mut query = sounds::table.select(sound_columns()).into_boxed();
+
+    if let Some(search) = filter.q.as_ref().map(|q| q.trim()) {
+        if !search.is_empty() {
+            query = query.filter(
+                sounds::name
+                    .like(format!("%{}%", escape_like(search)))
+                    .escape('\\'),
+            );
+        }
+    }
+
+    let slugs = filter.slugs();
+
+    if !slugs.is_empty() {
+        match filter.tag_match.unwrap_or(TagMatch::Any) {
+            TagMatch::Any => {
+                query = query.filter(
+                    sounds::id.eq_any(
+                        tags::table
+                            .select(tags::sound_id)
+                            .filter(tags::slug.eq_any(slugs)),
+                    ),
+                );
+            }
+            TagMatch::All => {
+                for slug in slugs {
+                    query = query.filter(
+                        sounds::id.eq_any(
+                            tags::table
+                                .select(tags::sound_id)
+                                .filter(tags::slug.eq(slug)),
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    query
+}
+
+/// Fetches one page of sounds matching the filter, starting right after
+/// the given cursor. The cursor must have been created for the same sort.
+pub fn fetch_sounds_page(
+    filter: &SoundsFilter,
+    cursor: Option<SoundsCursor>,
+    database_connection: &SqliteConnection,
+) -> Result<SoundsPage, diesel::result::Error> {
+    let sort = filter.sort();
+    let limit = filter.limit();
+
+    let total = filtered_sounds_query(filter)
+        .count()
+        .get_result::<i64>(database_connection)?;
+
+    let mut query = filtered_sounds_query(filter);
+    let query = match sort {
+        SoundsSort::Name => {
+            let after = match cursor {
+                Some(SoundsCursor::Name { value, id, .. }) => Some((value, id)),
+                _ => None,
+            };
+
+            sort_by!(query, sounds::name, after, filter.order())
+        }
+        SoundsSort::CreatedAt => {
+            let after = match cursor {
+                Some(SoundsCursor::CreatedAt { value, id, .. }) => Some((value, id)),
+                _ => None,
+            };
+
+            match filter.order() {
+                SortOrder::Asc => sort_by!(query, created_at_unknown_last(), after, SortOrder::Asc),
+                SortOrder::Desc => sort_by!(query, sounds::created_at, after, SortOrder::Desc),
+            }
+        }
+        SoundsSort::PlayCount => {
+            let after = match cursor {
+                Some(SoundsCursor::PlayCount { value, id, .. }) => Some((value, id)),
+                _ => None,
+            };
+
+            sort_by!(query, sounds::play_count, after, filter.order())
+        }
+    };
+
+    /*
+     * One extra sound is fetched to find
+     * out if there is a next page at all.
+     */
+    let mut sounds = query.limit(limit + 1).load::<Sound>(database_connection)?;
+    let has_next_page = sounds.len() as i64 > limit;
+    sounds.truncate(limit as usize);
+
+    let next_cursor = if has_next_page {
+        sounds
+            .last()
+            .map(|sound| SoundsCursor::after(sound, sort, filter.order()).encode())
+    } else {
+        None
+    };
+
+    let tags = Tag::belonging_to(&sounds)
+        .load::<Tag>(database_connection)?
+        .grouped_by(&sounds);
+
+    let items = sounds
+        .into_iter()
+        .zip(tags)
+        .map(|(sound, tags)| into_sound_with_tags(sound, tags))
+        .collect::<Vec<SoundWithTags>>();
+
+    Ok(SoundsPage {
+        items,
+        total,
+        next_cursor,
+    })
+}
+
+/// Fetches every sound matching the name search and tag filters, ordered by name.
+pub fn fetch_filtered_sounds(
+    filter: &SoundsFilter,
+    database_connection: &SqliteConnection,
+) -> Result<Vec<SoundWithTags>, diesel::result::Error> {
+    let sounds = filtered_sounds_query(filter)
+        .order((sounds::name.asc(), sounds::id.asc()))
+        .load::<Sound>(database_connection)?;
+
+    let tags = Tag::belonging_to(&sounds)
+        .load::<Tag>(database_connection)?
+        .grouped_by(&sounds);
+
+    let sounds = sounds
+        .into_iter()
+        .zip(tags)
+        .map(|(sound, tags)| into_sound_with_tags(sound, tags))
+        .collect::<Vec<SoundWithTags>>();
+
+    Ok(sounds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        actions::sounds::insert_sound,
+        test_utils::{database_connection, sound},
+    };
+
+    /// Sounds sharing names, play counts and upload dates, some of them unknown.
+    const TIED_SOUNDS: [(&str, &str, i32, i64); 8] = [
+        ("s1", "bell", 3, UNKNOWN_CREATED_AT),
+        ("s2", "alarm", 3, 100),
+        ("s3", "clap", 3, 100),
+        ("s4", "bell", 0, 100),
+        ("s5", "alarm", 0, UNKNOWN_CREATED_AT),
+        ("s6", "drum", 7, 200),
+        ("s7", "bell", 7, 200),
+        ("s8", "drum", 7, UNKNOWN_CREATED_AT),
+    ];
+
+    fn insert_tied_sounds(database_connection: &SqliteConnection) {
+        for (id, name, play_count, created_at) in TIED_SOUNDS {
+            let sound = Sound {
+                name: name.to_string(),
+                play_count,
+                created_at,
+                ..sound(id)
+            };
+            insert_sound(sound, vec![], database_connection).unwrap();
+        }
+    }
+
+    fn insert_named_sound(
+        id: &str,
+        name: &str,
+        slugs: &[&str],
+        database_connection: &SqliteConnection,
+    ) {
+        let sound = Sound {
+            name: name.to_string(),
+            ..sound(id)
+        };
+        let slugs = slugs.iter().map(|slug| slug.to_string()).collect();
+        insert_sound(sound, slugs, database_connection).unwrap();
+    }
+
+    /// Ids of every sound, fetched `limit` at a time by following the cursors.
+    fn page_through(
+        sort: SoundsSort,
+        order: SortOrder,
+        limit: i64,
+        database_connection: &SqliteConnection,
+    ) -> Vec<String> {
+        let mut filter = SoundsFilter {
+            sort: Some(sort),
+            order: Some(order),
+            limit: Some(limit),
+            ..SoundsFilter::default()
+        };
+        let mut ids = vec![];
+
+        loop {
+            let page =
+                fetch_sounds_page(&filter, filter.cursor().unwrap(), database_connection).unwrap();
+            assert_eq!(page.total, TIED_SOUNDS.len() as i64);
+            assert!(page.items.len() as i64 <= limit);
+            ids.extend(page.items.into_iter().map(|sound| sound.id));
+            // A cursor that doesn't move past its sound would page forever
+            assert!(ids.len() <= TIED_SOUNDS.len(), "Pages repeat sounds");
+
+            match page.next_cursor {
+                Some(cursor) => filter.cursor = Some(cursor),
+                None => return ids,
+            }
+        }
+    }
+
+    fn filtered_ids(filter: SoundsFilter, database_connection: &SqliteConnection) -> Vec<String> {
+        fetch_filtered_sounds(&filter, database_connection)
+            .unwrap()
+            .into_iter()
+            .map(|sound| sound.id)
+            .collect()
+    }
+
+    #[test]
+    fn cursors_round_trip() {
+        for cursor in [
+            SoundsCursor::Name {
+                value: "\"quoted\" bell, ünïcode".to_string(),
+                id: "s1".to_string(),
+                order: SortOrder::Asc,
+            },
+            SoundsCursor::CreatedAt {
+                value: i64::MAX,
+                id: "s2".to_string(),
+                order: SortOrder::Asc,
+            },
+            SoundsCursor::PlayCount {
+                value: 7,
+                id: "s3".to_string(),
+                order: SortOrder::Desc,
+            },
+        ] {
+            assert_eq!(SoundsCursor::decode(&cursor.encode()), Some(cursor));
+        }
+    }
+
+    #[test]
+    fn malformed_cursors_are_not_decoded() {
+        let incomplete =
+            r#"{"sort":"name"}"#.bytes().map(|byte| format!("{:02x}", byte)).collect::<String>();
+
+        for cursor in ["", "zz", "7b2", "not a cursor", &incomplete] {
+            assert_eq!(SoundsCursor::decode(cursor), None, "{}", cursor);
+        }
+    }
+
+    #[test]
+    fn cursors_must_match_the_sort_and_order() {
+        let play_count_cursor = |order| {
+            SoundsCursor::PlayCount {
+                value: 3,
+                id: "s1".to_string(),
+                order,
+            }
+            .encode()
+        };
+        let filter = |cursor: Option<String>| SoundsFilter {
+            sort: Some(SoundsSort::PlayCount),
+            cursor,
+            ..SoundsFilter::default()
+        };
+
+        assert_eq!(filter(None).cursor(), Ok(None));
+        assert_eq!(
+            filter(Some(play_count_cursor(SortOrder::Desc))).cursor(),
+            Ok(SoundsCursor::decode(&play_count_cursor(SortOrder::Desc)))
+        );
+        assert_eq!(
+            filter(Some(play_count_cursor(SortOrder::Asc))).cursor(),
+            Err(InvalidCursor)
+        );
+
+        let name_cursor = SoundsCursor::Name {
+            value: "bell".to_string(),
+            id: "s1".to_string(),
+            order: SortOrder::Desc,
+        };
+        assert_eq!(
+            filter(Some(name_cursor.encode())).cursor(),
+            Err(InvalidCursor)
+        );
+        assert_eq!(
+            filter(Some("not a cursor".to_string())).cursor(),
+            Err(InvalidCursor)
+        );
+    }
+
+    #[test]
+    fn pages_break_ties_by_id() {
+        let database_connection = database_connection();
+        insert_tied_sounds(&database_connection);
+
+        for (sort, order, expected) in [
+            (
+                SoundsSort::Name,
+                SortOrder::Asc,
+                ["s2", "s5", "s1", "s4", "s7", "s3", "s6", "s8"],
+            ),
+            (
+                SoundsSort::Name,
+                SortOrder::Desc,
+                ["s8", "s6", "s3", "s7", "s4", "s1", "s5", "s2"],
+            ),
+            (
+                SoundsSort::PlayCount,
+                SortOrder::Asc,
+                ["s4", "s5", "s1", "s2", "s3", "s6", "s7", "s8"],
+            ),
+            (
+                SoundsSort::PlayCount,
+                SortOrder::Desc,
+                ["s8", "s7", "s6", "s3", "s2", "s1", "s5", "s4"],
+            ),
+            // Unknown upload dates come last in both orders
+            (
+                SoundsSort::CreatedAt,
+                SortOrder::Asc,
+                ["s2", "s3", "s4", "s6", "s7", "s1", "s5", "s8"],
+            ),
+            (
+                SoundsSort::CreatedAt,
+                SortOrder::Desc,
+                ["s7", "s6", "s4", "s3", "s2", "s8", "s5", "s1"],
+            ),
+        ] {
+            // Every limit puts page boundaries inside groups of ties
+            for limit in 1..=TIED_SOUNDS.len() as i64 {
+                assert_eq!(
+                    page_through(sort, order, limit, &database_connection),
+                    expected,
+                    "{:?} {:?} by {}",
+                    sort,
+                    order,
+                    limit
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tags_match_any_or_all() {
+        let database_connection = database_connection();
+        insert_named_sound("both", "both", &["loud", "short"], &database_connection);
+        insert_named_sound("loud", "loud", &["loud"], &database_connection);
+        insert_named_sound("short", "short", &["short"], &database_connection);
+        insert_named_sound("none", "none", &[], &database_connection);
+
+        let filter = |tag_match| SoundsFilter {
+            tags: Some("loud,short".to_string()),
+            tag_match,
+            ..SoundsFilter::default()
+        };
+
+        assert_eq!(
+            filtered_ids(filter(None), &database_connection),
+            ["both", "loud", "short"]
+        );
+        assert_eq!(
+            filtered_ids(filter(Some(TagMatch::Any)), &database_connection),
+            ["both", "loud", "short"]
+        );
+        assert_eq!(
+            filtered_ids(filter(Some(TagMatch::All)), &database_connection),
+            ["both"]
+        );
+    }
+
+    #[test]
+    fn like_wildcards_are_escaped() {
+        assert_eq!(escape_like(r"50%_off\"), r"50\%\_off\\");
+        assert_eq!(escape_like("plain"), "plain");
+    }
+
+    #[test]
+    fn searches_match_wildcards_literally() {
+        let database_connection = database_connection();
+        for (id, name) in [
+            ("percent", "50% off"),
+            ("digits", "500 off"),
+            ("underscore", "snake_case"),
+            ("letter", "snakeXcase"),
+            ("backslash", r"back\slash"),
+            ("plain", "backslash"),
+        ] {
+            insert_named_sound(id, name, &[], &database_connection);
+        }
+
+        let search = |q: &str| SoundsFilter {
+            q: Some(q.to_string()),
+            ..SoundsFilter::default()
+        };
+
+        assert_eq!(
+            filtered_ids(search("50%"), &database_connection),
+            ["percent"]
+        );
+        assert_eq!(
+            filtered_ids(search("e_c"), &database_connection),
+            ["underscore"]
+        );
+        assert_eq!(
+            filtered_ids(search(r"k\s"), &database_connection),
+            ["backslash"]
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use std::{
+    io::{Error, ErrorKind},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use reqwest::{header::LOCATION, redirect::Policy, Client, Response, Url};
+
+/// How long fetching a remote file can take before giving up.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Redirects followed before giving up on a URL.
+const MAX_REDIRECTS: usize = 5;
+
+/// Client fetching `url` from `address`, the one its host was checked to resolve to.
+///
+/// Pinning the address keeps the client from resolving the host again, which
+/// could give another answer. Redirects aren't followed by the client,
+/// `get_remote_file` follows them itself to check where each one points to.
+fn pinned_client(url: &Url, address: SocketAddr) -> reqwest::Result<Client> {
+    let builder = Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .redirect(Policy::none());
+
+    match url.domain() {
+        Some(domain) => builder.resolve(domain, address),
+        // IP addresses aren't resolved, the client connects to the one checked
+        None => builder,
+    }
+    .build()
+}
+
+/// Whether an address can be reached from the internet, as opposed to
+/// loopback, private, link-local (cloud metadata included) or reserved ranges.
+pub fn is_public_address(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => is_public_ipv4(address),
+        IpAddr::V6(address) => match embedded_ipv4(address) {
+            Some(address) => is_public_ipv4(address),
+            None => is_public_ipv6(address),
+        },
+    }
+}
+
+/// IPv4 address an IPv6 one leads to, through mapping (`::ffff:0:0/96`),
+/// compatibility (`::/96`), NAT64 (`64:ff9b::/96`) or 6to4 (`2002::/16`).
+fn embedded_ipv4(address: Ipv6Addr) -> Option<Ipv4Addr> {
+    let segments = address.segments();
+    let [.., a, b, c, d] = address.octets();
+
+    match segments {
+        [0x64, 0xff9b, 0, 0, 0, 0, _, _] => Some(Ipv4Addr::new(a, b, c, d)),
+        [0x2002, high, low, ..] => Some(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low))),
+        _ => address.to_ipv4(),
+    }
+}
+
+fn is_public_ipv4(address: Ipv4Addr) -> bool {
+    let [first, second, third, _] = address.octets();
+
+    !(address.is_unspecified()
+        || address.is_loopback()
+        || address.is_private()
+        || address.is_link_local()
+        || address.is_broadcast()
+        || address.is_documentation()
+        || address.is_multicast()
+        // 0.0.0.0/8, 100.64.0.0/10 (carrier grade NAT), 192.0.0.0/24 and 240.0.0.0/4
+        || first == 0
+        || (first == 100 && (64..128).contains(&second))
+        || (first == 192 && second == 0 && third == 0)
+        || first >= 240)
+}
+
+fn is_public_ipv6(address: Ipv6Addr) -> bool {
+    let first_segment = address.segments()[0];
+
+    !(address.is_unspecified()
+        || address.is_loopback()
+        || address.is_multicast()
+        // fc00::/7 (unique local), fe80::/10 (link-local) and 2001:db8::/32 (documentation)
+        || (first_segment & 0xfe00) == 0xfc00
+        || (first_segment & 0xffc0) == 0xfe80
+        || (first_segment == 0x2001 && address.segments()[1] == 0x0db8))
+}
+
+/// Resolves the host of `url`, making sure every address it resolves to is allowed.
+async fn resolve_url_host(url: &Url, is_allowed: fn(IpAddr) -> bool) -> Result<SocketAddr, Error> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "URL has no host."))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    let addresses = tokio::net::lookup_host((host, port))
+        .await?
+        .collect::<Vec<_>>();
+
+    if !addresses.iter().all(|address| is_allowed(address.ip())) {
+        return Err(Error::new(
+            ErrorKind::PermissionDenied,
+            "URL points to an address that isn't reachable from the internet.",
+        ));
+    }
+
+    addresses
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("Failed to resolve {}.", host)))
+}
+
+/// Sends a GET request to `url`, following redirects, as long as every
+/// host involved only resolves to addresses accepted by `is_allowed`.
+///
+/// Each host is resolved once and connected to at the address that was checked,
+/// so a DNS answer changing in between can't point the request elsewhere.
+pub async fn get_remote_file(url: Url, is_allowed: fn(IpAddr) -> bool) -> Result<Response, Error> {
+    let mut url = url;
+
+    for _ in 0..=MAX_REDIRECTS {
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Only http and https URLs can be fetched.",
+            ));
+        }
+
+        let address = resolve_url_host(&url, is_allowed).await?;
+
+        let response = pinned_client(&url, address)
+            .map_err(Error::other)?
+            .get(url.clone())
+            .send()
+            .await
+            .map_err(Error::other)?;
+
+        if !response.status().is_redirection() {
+            return response.error_for_status().map_err(Error::other);
+        }
+
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Redirect has no location."))?;
+
+        url = url
+            .join(location)
+            .map_err(|reason| Error::new(ErrorKind::InvalidData, reason))?;
+    }
+
+    Err(Error::other(format!(
+        "URL redirected more than {} times.",
+        MAX_REDIRECTS
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for address in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public_address(address.parse().unwrap()), "{}", address);
+        }
+    }
+
+    #[test]
+    fn documentation_ipv6_addresses_are_not_public() {
+        assert!(!is_public_address("2001:db8::1".parse().unwrap()));
+        assert!(!is_public_address("2001:db8:ffff::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn nat64_addresses_are_checked_as_ipv4() {
+        assert!(!is_public_address("64:ff9b::127.0.0.1".parse().unwrap()));
+        assert!(!is_public_address("64:ff9b::a9fe:a9fe".parse().unwrap()));
+        assert!(is_public_address("64:ff9b::1.1.1.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn six_to_four_addresses_are_checked_as_ipv4() {
+        assert!(!is_public_address("2002:7f00:1::".parse().unwrap()));
+        assert!(!is_public_address("2002:c0a8:101::1".parse().unwrap()));
+        assert!(is_public_address("2002:101:101::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn ipv4_compatible_addresses_are_checked_as_ipv4() {
+        assert!(!is_public_address("::127.0.0.1".parse().unwrap()));
+        assert!(!is_public_address("::10.0.0.1".parse().unwrap()));
+        assert!(is_public_address("::1.1.1.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn internet_addresses_are_public() {
+        for address in ["1.1.1.1", "93.184.216.34", "2606:4700:4700::1111"] {
+            assert!(is_public_address(address.parse().unwrap()), "{}", address);
+        }
+    }
+}
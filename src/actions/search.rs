@@ -0,0 +1,163 @@
+use diesel::{
+    prelude::*,
+    sql_query,
+    sql_types::{BigInt, Text},
+};
+
+use crate::{
+    actions::sounds::{into_sound_with_tags, sound_columns},
+    models::{Sound, SoundWithTags, Tag},
+    schema::sounds,
+};
+
+pub const DEFAULT_SEARCH_LIMIT: i64 = 25;
+pub const MAX_SEARCH_LIMIT: i64 = 100;
+
+/// Sounds listed in reply to the bots' search commands.
+pub const SEARCH_RESULT_LIMIT: i64 = 10;
+
+#[derive(QueryableByName)]
+struct SoundSearchMatch {
+    #[sql_type = "Text"]
+    sound_id: String,
+}
+
+/// Turns free text into an FTS5 query where every word
+/// is matched as a prefix, so "brr" finds "brrrr-sound".
+fn into_fts_query(search: &str) -> Option<String> {
+    let terms = search
+        .split(|character: char| !character.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("\"{}\"*", term.to_lowercase()))
+        .collect::<Vec<_>>();
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Searches sound names and tags through the `sounds_fts` index,
+/// returning the best ranked sounds first.
+pub fn search_sounds(
+    search: &str,
+    limit: i64,
+    database_connection: &SqliteConnection,
+) -> Result<Vec<SoundWithTags>, diesel::result::Error> {
+    let fts_query = match into_fts_query(search) {
+        Some(fts_query) => fts_query,
+        None => return Ok(vec![]),
+    };
+
+    let sound_ids =
+        sql_query("SELECT sound_id FROM sounds_fts WHERE sounds_fts MATCH ? ORDER BY rank LIMIT ?")
+            .bind::<Text, _>(fts_query)
+            .bind::<BigInt, _>(limit)
+            .load::<SoundSearchMatch>(database_connection)?
+            .into_iter()
+            .map(|search_match| search_match.sound_id)
+            .collect::<Vec<_>>();
+
+    let sounds = sounds::table
+        .select(sound_columns())
+        .filter(sounds::id.eq_any(sound_ids.clone()))
+        .load::<Sound>(database_connection)?;
+
+    let tags = Tag::belonging_to(&sounds)
+        .load::<Tag>(database_connection)?
+        .grouped_by(&sounds);
+
+    let mut results = sounds
+        .into_iter()
+        .zip(tags)
+        .map(|(sound, tags)| into_sound_with_tags(sound, tags))
+        .collect::<Vec<SoundWithTags>>();
+
+    results.sort_by_key(|sound| sound_ids.iter().position(|id| id == &sound.id));
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        actions::sounds::insert_sound,
+        test_utils::{database_connection, sound},
+    };
+
+    fn searched_ids(search: &str, database_connection: &SqliteConnection) -> Vec<String> {
+        search_sounds(search, SEARCH_RESULT_LIMIT, database_connection)
+            .unwrap()
+            .into_iter()
+            .map(|sound| sound.id)
+            .collect()
+    }
+
+    #[test]
+    fn words_are_matched_as_prefixes() {
+        assert_eq!(into_fts_query("brr"), Some(r#""brr"*"#.to_string()));
+        assert_eq!(
+            into_fts_query("Airhorn  LOUD"),
+            Some(r#""airhorn"* "loud"*"#.to_string())
+        );
+    }
+
+    #[test]
+    fn fts_syntax_is_matched_literally() {
+        for (search, fts_query) in [
+            (r#"say "hi""#, r#""say"* "hi"*"#),
+            ("brr*", r#""brr"*"#),
+            ("-loud", r#""loud"*"#),
+            ("brrrr-sound", r#""brrrr"* "sound"*"#),
+            ("cats OR dogs", r#""cats"* "or"* "dogs"*"#),
+            ("NEAR(cats dogs)", r#""near"* "cats"* "dogs"*"#),
+            ("name:cats", r#""name"* "cats"*"#),
+        ] {
+            assert_eq!(
+                into_fts_query(search),
+                Some(fts_query.to_string()),
+                "{}",
+                search
+            );
+        }
+    }
+
+    #[test]
+    fn empty_searches_have_no_query() {
+        for search in ["", "   ", "\t\n", "\"*-", "()"] {
+            assert_eq!(into_fts_query(search), None, "{:?}", search);
+        }
+    }
+
+    #[test]
+    fn sounds_are_found_by_name_and_tag_prefixes() {
+        let database_connection = database_connection();
+        insert_sound(
+            Sound {
+                name: "brrrr-sound".to_string(),
+                ..sound("brrrr")
+            },
+            vec![],
+            &database_connection,
+        )
+        .unwrap();
+        insert_sound(
+            Sound {
+                name: "airhorn".to_string(),
+                ..sound("airhorn")
+            },
+            vec!["loud".to_string()],
+            &database_connection,
+        )
+        .unwrap();
+
+        assert_eq!(searched_ids("brr", &database_connection), ["brrrr"]);
+        assert_eq!(searched_ids("Sou", &database_connection), ["brrrr"]);
+        assert_eq!(searched_ids("lou", &database_connection), ["airhorn"]);
+        assert!(searched_ids("brr loud", &database_connection).is_empty());
+        assert!(searched_ids("   ", &database_connection).is_empty());
+        assert!(searched_ids(r#"NEAR("brr" OR -air"#, &database_connection).is_empty());
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashSet;
+
+use diesel::{delete, prelude::*, update, SqliteConnection};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use crate::{models::Tag, schema::tags};
+
+pub const MAX_SLUG_LENGTH: usize = 32;
+
+/// Turns a user provided tag into its slug form.
+///
+/// Accents are folded, letters are lowercased and any run of characters
+/// other than letters and digits becomes a single hyphen. Returns `None`
+/// for tags that end up empty.
+pub fn normalize_slug(tag: &str) -> Option<String> {
+    let folded = tag
+        .nfkd()
+        .filter(|character| !is_combining_mark(*character))
+        .collect::<String>()
+        .to_lowercase();
+
+    let slug = folded
+        .split(|character: char| !character.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+        .chars()
+        .take(MAX_SLUG_LENGTH)
+        .collect::<String>();
+
+    let slug = slug.trim_end_matches('-');
+
+    if slug.is_empty() {
+        None
+    } else {
+        Some(slug.to_string())
+    }
+}
+
+/// Normalizes every tag, dropping empty and duplicated slugs
+/// while keeping the original order.
+pub fn normalize_slugs(tags: Vec<String>) -> Vec<String> {
+    let mut slugs: Vec<String> = Vec::new();
+
+    for slug in tags.iter().filter_map(|tag| normalize_slug(tag)) {
+        if !slugs.contains(&slug) {
+            slugs.push(slug);
+        }
+    }
+
+    slugs
+}
+
+/// Rewrites the stored slugs that `normalize_slug` would change, for tags
+/// stored before slugs were normalized. Tags that end up empty, or on a slug
+/// their sound already has, are removed. Returns how many tags were changed.
+pub fn normalize_stored_slugs(database_connection: &SqliteConnection) -> QueryResult<usize> {
+    database_connection.transaction(|| {
+        let stored_tags = tags::table.load::<Tag>(database_connection)?;
+        let mut sound_slugs = stored_tags
+            .iter()
+            .map(|tag| (tag.sound_id.clone(), tag.slug.clone()))
+            .collect::<HashSet<_>>();
+        let mut changed = 0;
+
+        for tag in stored_tags {
+            let slug = normalize_slug(&tag.slug);
+
+            if slug.as_ref() == Some(&tag.slug) {
+                continue;
+            }
+
+            match slug {
+                Some(slug) if sound_slugs.insert((tag.sound_id.clone(), slug.clone())) => {
+                    update(tags::table.filter(tags::id.eq(&tag.id)))
+                        .set(tags::slug.eq(slug))
+                        .execute(database_connection)?;
+                }
+                _ => {
+                    delete(tags::table.filter(tags::id.eq(&tag.id)))
+                        .execute(database_connection)?;
+                }
+            }
+
+            changed += 1;
+        }
+
+        Ok(changed)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        actions::sounds::insert_sound,
+        test_utils::{database_connection, sound},
+    };
+
+    #[test]
+    fn slugs_are_trimmed_and_lowercased() {
+        assert_eq!(normalize_slug("  Meme \t"), Some("meme".to_string()));
+        assert_eq!(normalize_slug("LOUD"), Some("loud".to_string()));
+    }
+
+    #[test]
+    fn accents_are_folded() {
+        assert_eq!(normalize_slug("Éclair"), Some("eclair".to_string()));
+        assert_eq!(
+            normalize_slug("ﬁnal Straße"),
+            Some("final-straße".to_string())
+        );
+    }
+
+    #[test]
+    fn whitespace_and_punctuation_collapse_into_hyphens() {
+        assert_eq!(
+            normalize_slug("rock  &\troll"),
+            Some("rock-roll".to_string())
+        );
+        assert_eq!(
+            normalize_slug("--bad__news!!"),
+            Some("bad-news".to_string())
+        );
+        assert_eq!(normalize_slug("don't stop"), Some("don-t-stop".to_string()));
+    }
+
+    #[test]
+    fn slugs_are_cut_at_the_length_limit() {
+        assert_eq!(
+            normalize_slug(&"a".repeat(MAX_SLUG_LENGTH + 8)),
+            Some("a".repeat(MAX_SLUG_LENGTH))
+        );
+        // A cut right after a separator doesn't leave a trailing hyphen
+        assert_eq!(
+            normalize_slug(&format!("{} bc", "a".repeat(MAX_SLUG_LENGTH - 1))),
+            Some("a".repeat(MAX_SLUG_LENGTH - 1))
+        );
+    }
+
+    #[test]
+    fn empty_slugs_are_dropped() {
+        for tag in ["", "   ", "-", "!?&", "\u{301}"] {
+            assert_eq!(normalize_slug(tag), None, "{:?}", tag);
+        }
+        assert_eq!(
+            normalize_slugs(vec![
+                "Meme".to_string(),
+                " ".to_string(),
+                "meme ".to_string()
+            ]),
+            vec!["meme"]
+        );
+    }
+
+    #[test]
+    fn stored_slugs_are_normalized_and_merged() {
+        let database_connection = database_connection();
+        insert_sound(sound("sound"), vec![], &database_connection).unwrap();
+        for slug in ["meme", "Meme", "meme ", "Café  Bar", "!!!"] {
+            diesel::insert_into(tags::table)
+                .values(Tag {
+                    id: slug.to_string(),
+                    sound_id: "sound".to_string(),
+                    slug: slug.to_string(),
+                })
+                .execute(&database_connection)
+                .unwrap();
+        }
+
+        assert_eq!(normalize_stored_slugs(&database_connection), Ok(4));
+        assert_eq!(normalize_stored_slugs(&database_connection), Ok(0));
+
+        let mut slugs = tags::table
+            .select(tags::slug)
+            .load::<String>(&database_connection)
+            .unwrap();
+        slugs.sort();
+        assert_eq!(slugs, vec!["cafe-bar", "meme"]);
+    }
+}
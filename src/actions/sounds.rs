@@ -1,17 +1,84 @@
-use diesel::{insert_into, prelude::*};
+use std::ops::RangeInclusive;
+
+use diesel::{
+    delete,
+    dsl::sql,
+    expression::SqlLiteral,
+    insert_into,
+    prelude::*,
+    sql_types::{BigInt, Binary, Nullable},
+    update,
+};
 use uuid::Uuid;
 
 use crate::{
-    models::{Sound, SoundWithTags, Tag},
+    actions::{
+        audio::{AudioMetadata, Loudness},
+        fingerprint::decode_fingerprint,
+        slugs::normalize_slugs,
+    },
+    models::{Sound, SoundFileChangeset, SoundWithTags, Tag},
     schema::sounds,
     schema::sounds::dsl::sounds as sounds_dsl,
+    schema::tags,
     schema::tags::dsl::tags as tags_dsl,
 };
 
+pub type SoundColumns = (
+    sounds::id,
+    sounds::name,
+    sounds::extension,
+    sounds::file_name,
+    sounds::file_hash,
+    sounds::created_at,
+    sounds::play_count,
+    sounds::playback_file_name,
+    sounds::loudness_lufs,
+    sounds::duration_ms,
+    sounds::sample_rate,
+    sounds::channels,
+    sounds::codec,
+    sounds::original_file_name,
+    sounds::original_extension,
+    sounds::original_file_hash,
+    SqlLiteral<Nullable<Binary>>,
+    sounds::true_peak_dbtp,
+);
+
+/// Columns a `Sound` is loaded from, with the fingerprint selected as `NULL`.
+///
+/// Fingerprints are only needed to look for duplicates, which
+/// `fetch_fingerprints` does on its own, so sounds loaded
+/// through any other query leave the BLOB out.
+pub fn sound_columns() -> SoundColumns {
+    (
+        sounds::id,
+        sounds::name,
+        sounds::extension,
+        sounds::file_name,
+        sounds::file_hash,
+        sounds::created_at,
+        sounds::play_count,
+        sounds::playback_file_name,
+        sounds::loudness_lufs,
+        sounds::duration_ms,
+        sounds::sample_rate,
+        sounds::channels,
+        sounds::codec,
+        sounds::original_file_name,
+        sounds::original_extension,
+        sounds::original_file_hash,
+        sql::<Nullable<Binary>>("NULL"),
+        sounds::true_peak_dbtp,
+    )
+}
+
 pub fn fetch_sounds_with_tags(
     database_connection: &SqliteConnection,
 ) -> Result<Vec<SoundWithTags>, diesel::result::Error> {
-    let sounds = sounds::table.load::<Sound>(database_connection)?;
+    let sounds = sounds::table
+        .select(sound_columns())
+        .load::<Sound>(database_connection)?;
     let tags = Tag::belonging_to(&sounds)
         .load::<Tag>(database_connection)?
         .grouped_by(&sounds);
@@ -19,24 +86,46 @@ pub fn fetch_sounds_with_tags(
     let data = sounds.into_iter().zip(tags);
     let sounds = data
         .into_iter()
-        .map(|(x, tags)| SoundWithTags {
-            extension: format!(".{}", x.extension),
-            file_name: x.file_name,
-            file_hash: x.file_hash,
-            id: x.id,
-            name: x.name,
-            tags: tags.into_iter().map(|tag| tag.slug).collect(),
-        })
+        .map(|(sound, tags)| into_sound_with_tags(sound, tags))
         .collect::<Vec<SoundWithTags>>();
 
     Ok(sounds)
 }
 
+pub fn fetch_sounds(
+    database_connection: &SqliteConnection,
+) -> Result<Vec<Sound>, diesel::result::Error> {
+    sounds::table
+        .select(sound_columns())
+        .order(sounds::name.asc())
+        .load::<Sound>(database_connection)
+}
+
+pub fn into_sound_with_tags(sound: Sound, tags: Vec<Tag>) -> SoundWithTags {
+    SoundWithTags {
+        extension: format!(".{}", sound.extension),
+        file_name: sound.file_name,
+        file_hash: sound.file_hash,
+        id: sound.id,
+        name: sound.name,
+        created_at: sound.created_at,
+        play_count: sound.play_count,
+        playback_file_name: sound.playback_file_name,
+        duration_ms: sound.duration_ms,
+        sample_rate: sound.sample_rate,
+        channels: sound.channels,
+        codec: sound.codec,
+        edited: sound.original_file_name.is_some(),
+        tags: tags.into_iter().map(|tag| tag.slug).collect(),
+    }
+}
+
 pub fn fetch_sound_by_id(
     sound_id: String,
     database_connection: &SqliteConnection,
 ) -> Option<Sound> {
     sounds::table
+        .select(sound_columns())
         .filter(sounds::id.eq(sound_id))
         .first::<Sound>(database_connection)
         .optional()
@@ -48,6 +137,7 @@ pub fn fetch_sound_with_tags_by_id(
     database_connection: &SqliteConnection,
 ) -> Option<SoundWithTags> {
     let query_result = sounds::table
+        .select(sound_columns())
         .filter(sounds::id.eq(sound_id))
         .first::<Sound>(database_connection)
         .optional()
@@ -60,14 +150,7 @@ pub fn fetch_sound_with_tags_by_id(
         .load::<Tag>(database_connection)
         .expect("Failed to fetch tags");
 
-    Some(SoundWithTags {
-        extension: format!(".{}", sound.extension),
-        file_name: sound.file_name,
-        file_hash: sound.file_hash,
-        id: sound.id,
-        name: sound.name,
-        tags: tags.into_iter().map(|tag| tag.slug).collect(),
-    })
+    Some(into_sound_with_tags(sound, tags))
 }
 
 pub fn fetch_sound_by_hash(
@@ -75,14 +158,25 @@ pub fn fetch_sound_by_hash(
     database_connection: &SqliteConnection,
 ) -> Option<Sound> {
     sounds::table
-        .filter(sounds::file_hash.eq(&file_hash))
+        .select(sound_columns())
+        .filter(
+            sounds::file_hash
+                .eq(&file_hash)
+                .or(sounds::original_file_hash.eq(&file_hash)),
+        )
         .first::<Sound>(database_connection)
         .optional()
         .expect("Failed to query by hash")
 }
 
-pub fn insert_sound(sound: Sound, slugs: Vec<String>, database_connection: &SqliteConnection) {
-    let tag_records = slugs
+/// Inserts a sound along with its tags, inside a single transaction
+/// so a failure while inserting tags also reverts the sound.
+pub fn insert_sound(
+    sound: Sound,
+    slugs: Vec<String>,
+    database_connection: &SqliteConnection,
+) -> QueryResult<()> {
+    let tag_records = normalize_slugs(slugs)
         .into_iter()
         .map(|slug| Tag {
             sound_id: sound.id.clone(),
@@ -91,14 +185,274 @@ pub fn insert_sound(sound: Sound, slugs: Vec<String>, database_connection: &Sqli
         })
         .collect::<Vec<_>>();
 
-    insert_into(sounds_dsl)
-        .values(sound)
+    database_connection.transaction(|| {
+        insert_into(sounds_dsl)
+            .values(sound)
+            .execute(database_connection)?;
+
+        insert_into(tags_dsl)
+            .values(tag_records)
+            // https://github.com/diesel-rs/diesel/issues/1822
+            .execute(database_connection)?;
+
+        Ok(())
+    })
+}
+
+pub fn delete_sound(
+    sound_id: String,
+    database_connection: &SqliteConnection,
+) -> Result<usize, diesel::result::Error> {
+    /*
+     * Tags are removed by the `ON DELETE CASCADE`
+     * constraint on `tags.sound_id`
+     */
+    delete(sounds::table.filter(sounds::id.eq(sound_id))).execute(database_connection)
+}
+
+/// Renames a sound and/or replaces its whole tag set.
+///
+/// Both changes are applied inside a single transaction,
+/// so a failure while replacing tags also reverts the rename.
+pub fn update_sound(
+    sound_id: String,
+    name: Option<String>,
+    slugs: Option<Vec<String>>,
+    database_connection: &SqliteConnection,
+) -> Result<Option<SoundWithTags>, diesel::result::Error> {
+    database_connection.transaction(|| {
+        let sound = fetch_sound_by_id(sound_id.clone(), database_connection);
+
+        if sound.is_none() {
+            return Ok(None);
+        }
+
+        if let Some(name) = name {
+            update(sounds::table.filter(sounds::id.eq(&sound_id)))
+                .set(sounds::name.eq(name))
+                .execute(database_connection)?;
+        }
+
+        if let Some(slugs) = slugs {
+            let tag_records = normalize_slugs(slugs)
+                .into_iter()
+                .map(|slug| Tag {
+                    sound_id: sound_id.clone(),
+                    id: Uuid::new_v4().to_string(),
+                    slug,
+                })
+                .collect::<Vec<_>>();
+
+            delete(tags::table.filter(tags::sound_id.eq(&sound_id)))
+                .execute(database_connection)?;
+
+            insert_into(tags_dsl)
+                .values(tag_records)
+                // https://github.com/diesel-rs/diesel/issues/1822
+                .execute(database_connection)?;
+        }
+
+        Ok(fetch_sound_with_tags_by_id(sound_id, database_connection))
+    })
+}
+
+pub fn increment_play_count(
+    sound_id: String,
+    database_connection: &SqliteConnection,
+) -> Result<usize, diesel::result::Error> {
+    update(sounds::table.filter(sounds::id.eq(sound_id)))
+        .set(sounds::play_count.eq(sounds::play_count + 1))
+        .execute(database_connection)
+}
+
+pub fn set_sound_playback_file_name(
+    sound_id: String,
+    playback_file_name: String,
+    database_connection: &SqliteConnection,
+) -> Result<usize, diesel::result::Error> {
+    update(sounds::table.filter(sounds::id.eq(sound_id)))
+        .set(sounds::playback_file_name.eq(playback_file_name))
+        .execute(database_connection)
+}
+
+pub fn set_sound_loudness(
+    sound_id: String,
+    loudness: Loudness,
+    database_connection: &SqliteConnection,
+) -> Result<usize, diesel::result::Error> {
+    update(sounds::table.filter(sounds::id.eq(sound_id)))
+        .set((
+            sounds::loudness_lufs.eq(loudness.integrated_lufs),
+            sounds::true_peak_dbtp.eq(loudness.true_peak_dbtp),
+        ))
+        .execute(database_connection)
+}
+
+pub fn set_sound_metadata(
+    sound_id: String,
+    metadata: AudioMetadata,
+    database_connection: &SqliteConnection,
+) -> Result<usize, diesel::result::Error> {
+    update(sounds::table.filter(sounds::id.eq(sound_id)))
+        .set((
+            sounds::duration_ms.eq(metadata.duration_ms),
+            sounds::sample_rate.eq(metadata.sample_rate),
+            sounds::channels.eq(metadata.channels),
+            sounds::codec.eq(metadata.codec),
+        ))
         .execute(database_connection)
-        .expect("Failed to insert sound in database.");
+}
+
+pub fn set_sound_file(
+    sound_id: String,
+    changeset: SoundFileChangeset,
+    database_connection: &SqliteConnection,
+) -> Result<Option<SoundWithTags>, diesel::result::Error> {
+    update(sounds::table.filter(sounds::id.eq(&sound_id)))
+        .set(changeset)
+        .execute(database_connection)?;
+
+    Ok(fetch_sound_with_tags_by_id(sound_id, database_connection))
+}
+
+/// Fingerprints of every sound that has one, keyed by sound id.
+pub fn fetch_fingerprints(
+    database_connection: &SqliteConnection,
+) -> Result<Vec<(String, Vec<u32>)>, diesel::result::Error> {
+    let fingerprints = sounds::table
+        .select((sounds::id, sounds::fingerprint))
+        .filter(sounds::fingerprint.is_not_null())
+        .load::<(String, Option<Vec<u8>>)>(database_connection)?;
+
+    Ok(decode_fingerprints(fingerprints))
+}
+
+/// Fingerprints made of a number of sub-fingerprints within `lengths`,
+/// so a new sound is only compared to the ones it could match.
+pub fn fetch_fingerprints_by_length(
+    lengths: RangeInclusive<usize>,
+    database_connection: &SqliteConnection,
+) -> Result<Vec<(String, Vec<u32>)>, diesel::result::Error> {
+    // Sub-fingerprints are stored on 4 bytes each
+    let fingerprint_bytes = sql::<BigInt>("length(sounds.fingerprint)");
 
-    insert_into(tags_dsl)
-        .values(tag_records)
-        // https://github.com/diesel-rs/diesel/issues/1822
+    let fingerprints = sounds::table
+        .select((sounds::id, sounds::fingerprint))
+        .filter(sounds::fingerprint.is_not_null())
+        .filter(
+            fingerprint_bytes.between((*lengths.start() * 4) as i64, (*lengths.end() * 4) as i64),
+        )
+        .load::<(String, Option<Vec<u8>>)>(database_connection)?;
+
+    Ok(decode_fingerprints(fingerprints))
+}
+
+fn decode_fingerprints(fingerprints: Vec<(String, Option<Vec<u8>>)>) -> Vec<(String, Vec<u32>)> {
+    fingerprints
+        .into_iter()
+        .filter_map(|(sound_id, fingerprint)| {
+            fingerprint.map(|fingerprint| (sound_id, decode_fingerprint(&fingerprint)))
+        })
+        .collect()
+}
+
+/// Ids of the sounds that weren't fingerprinted yet.
+pub fn fetch_unfingerprinted_sound_ids(
+    database_connection: &SqliteConnection,
+) -> Result<Vec<String>, diesel::result::Error> {
+    sounds::table
+        .select(sounds::id)
+        .filter(sounds::fingerprint.is_null())
+        .load::<String>(database_connection)
+}
+
+pub fn set_sound_fingerprint(
+    sound_id: String,
+    fingerprint: Vec<u8>,
+    database_connection: &SqliteConnection,
+) -> Result<usize, diesel::result::Error> {
+    update(sounds::table.filter(sounds::id.eq(sound_id)))
+        .set(sounds::fingerprint.eq(fingerprint))
         .execute(database_connection)
-        .expect("Failed to insert tags in database.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        actions::fingerprint::comparable_lengths,
+        test_utils::{self, database_connection},
+    };
+
+    fn sound(id: &str, fingerprint_length: Option<usize>) -> Sound {
+        Sound {
+            fingerprint: fingerprint_length.map(|length| vec![0; length * 4]),
+            ..test_utils::sound(id)
+        }
+    }
+
+    #[test]
+    fn fingerprints_are_only_fetched_for_comparable_lengths() {
+        let database_connection = database_connection();
+        for (id, length) in [("short", 70), ("same", 100), ("long", 125), ("longer", 130)] {
+            insert_sound(sound(id, Some(length)), vec![], &database_connection).unwrap();
+        }
+
+        let mut sound_ids =
+            fetch_fingerprints_by_length(comparable_lengths(100), &database_connection)
+                .unwrap()
+                .into_iter()
+                .map(|(sound_id, _)| sound_id)
+                .collect::<Vec<_>>();
+        sound_ids.sort();
+
+        assert_eq!(sound_ids, vec!["long", "same"]);
+    }
+
+    #[test]
+    fn sounds_are_loaded_without_their_fingerprint() {
+        let database_connection = database_connection();
+        insert_sound(
+            sound("fingerprinted", Some(10)),
+            vec![],
+            &database_connection,
+        )
+        .unwrap();
+        insert_sound(sound("pending", None), vec![], &database_connection).unwrap();
+
+        let sound = fetch_sound_by_id("fingerprinted".to_string(), &database_connection).unwrap();
+
+        assert!(sound.fingerprint.is_none());
+        assert_eq!(fetch_sounds(&database_connection).unwrap().len(), 2);
+        assert_eq!(
+            fetch_unfingerprinted_sound_ids(&database_connection).unwrap(),
+            vec!["pending"]
+        );
+    }
+
+    #[test]
+    fn failed_insert_leaves_no_tags_behind() {
+        let database_connection = database_connection();
+        insert_sound(
+            sound("taken", None),
+            vec!["first".to_string()],
+            &database_connection,
+        )
+        .unwrap();
+
+        let result = insert_sound(
+            sound("taken", None),
+            vec!["second".to_string()],
+            &database_connection,
+        );
+
+        assert!(result.is_err());
+        assert_eq!(
+            tags::table
+                .select(tags::slug)
+                .load::<String>(&database_connection)
+                .unwrap(),
+            vec!["first"]
+        );
+    }
 }
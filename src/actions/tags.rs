@@ -1,34 +1,71 @@
-use actix_web::{web, Error};
-use diesel::{insert_into, prelude::*};
+use actix_web::{error::ErrorInternalServerError, web, Error};
+use diesel::{delete, insert_or_ignore_into, prelude::*, sql_query};
+use serde::Deserialize;
 use uuid::Uuid;
 
 use crate::{
     app_state::DatabasePool,
-    models::{SoundWithTags, Tag},
+    models::{SoundWithTags, Tag, TagWithCount},
+    schema::tags,
 };
 
-use crate::{actions::sounds::fetch_sound_with_tags_by_id, schema::tags::dsl::tags as tags_dsl};
+use crate::{
+    actions::{
+        slugs::normalize_slugs,
+        sounds::{fetch_sound_by_id, fetch_sound_with_tags_by_id},
+    },
+    schema::tags::dsl::tags as tags_dsl,
+};
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum TagsSort {
+    Count,
+    Name,
+}
+
+pub enum RemoveTagsResult {
+    Removed(Box<SoundWithTags>),
+    SoundNotFound,
+    TagsNotFound(Vec<String>),
+}
 
 pub async fn insert_tags(
     sound_id: String,
     slugs: Vec<String>,
     database_pool: DatabasePool,
-) -> Result<SoundWithTags, Error> {
-    let tag_records = slugs
-        .into_iter()
-        .map(|slug| Tag {
-            sound_id: sound_id.clone(),
-            id: Uuid::new_v4().to_string(),
-            slug,
-        })
-        .collect::<Vec<_>>();
+) -> Result<Option<SoundWithTags>, Error> {
+    let slugs = normalize_slugs(slugs);
 
     let sound = web::block(move || {
         let database_connection = database_pool
             .get()
             .expect("Failed to get db connection from db pool");
 
-        insert_into(tags_dsl)
+        fetch_sound_by_id(sound_id.clone(), &database_connection)?;
+
+        /*
+         * Slugs the sound already carries are skipped here,
+         * the unique index on `(sound_id, slug)` covers
+         * concurrent requests adding the same slug.
+         */
+        let existing_slugs = tags::table
+            .filter(tags::sound_id.eq(&sound_id))
+            .select(tags::slug)
+            .load::<String>(&*database_connection)
+            .expect("Failed to fetch tags");
+
+        let tag_records = slugs
+            .into_iter()
+            .filter(|slug| !existing_slugs.contains(slug))
+            .map(|slug| Tag {
+                sound_id: sound_id.clone(),
+                id: Uuid::new_v4().to_string(),
+                slug,
+            })
+            .collect::<Vec<_>>();
+
+        insert_or_ignore_into(tags_dsl)
             .values(tag_records)
             // https://github.com/diesel-rs/diesel/issues/1822
             .execute(&*database_connection)
@@ -38,5 +75,76 @@ pub async fn insert_tags(
     })
     .await?;
 
-    Ok(sound.unwrap())
+    Ok(sound)
+}
+
+/// Removes the given slugs from a sound.
+///
+/// Nothing is removed unless the sound carries every one of the given slugs.
+pub async fn remove_tags(
+    sound_id: String,
+    slugs: Vec<String>,
+    database_pool: DatabasePool,
+) -> Result<RemoveTagsResult, Error> {
+    let slugs = normalize_slugs(slugs);
+    let result = web::block(move || {
+        let database_connection = database_pool
+            .get()
+            .expect("Failed to get db connection from db pool");
+
+        database_connection.transaction(|| {
+            if fetch_sound_by_id(sound_id.clone(), &database_connection).is_none() {
+                return Ok(RemoveTagsResult::SoundNotFound);
+            }
+
+            let existing_slugs = tags::table
+                .filter(tags::sound_id.eq(&sound_id))
+                .select(tags::slug)
+                .load::<String>(&*database_connection)?;
+
+            let missing_slugs = slugs
+                .iter()
+                .filter(|slug| !existing_slugs.contains(slug))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if !missing_slugs.is_empty() {
+                return Ok(RemoveTagsResult::TagsNotFound(missing_slugs));
+            }
+
+            delete(
+                tags::table
+                    .filter(tags::sound_id.eq(&sound_id))
+                    .filter(tags::slug.eq_any(slugs)),
+            )
+            .execute(&*database_connection)?;
+
+            let sound = fetch_sound_with_tags_by_id(sound_id, &database_connection)
+                .expect("Sound to exist after removing its tags");
+
+            Ok::<_, diesel::result::Error>(RemoveTagsResult::Removed(Box::new(sound)))
+        })
+    })
+    .await?;
+
+    result.map_err(ErrorInternalServerError)
+}
+
+/// Lists every slug in use along with the amount of sounds carrying it.
+///
+/// Sorting by count puts the most used slugs first.
+pub fn fetch_tags_with_count(
+    sort: TagsSort,
+    database_connection: &SqliteConnection,
+) -> Result<Vec<TagWithCount>, diesel::result::Error> {
+    let order_by = match sort {
+        TagsSort::Count => "count DESC, slug ASC",
+        TagsSort::Name => "slug ASC",
+    };
+
+    sql_query(format!(
+        "SELECT slug, COUNT(DISTINCT sound_id) AS count FROM tags GROUP BY slug ORDER BY {}",
+        order_by
+    ))
+    .load::<TagWithCount>(database_connection)
 }
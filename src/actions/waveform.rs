@@ -0,0 +1,216 @@
+use std::{
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::models::Sound;
+
+/// Amount of min/max pairs in a waveform.
+pub const WAVEFORM_LENGTH: usize = 256;
+
+/// Sample rate the audio is decoded at before computing peaks,
+/// which is plenty for a thumbnail and keeps decoding cheap.
+const WAVEFORM_SAMPLE_RATE: &str = "8000";
+
+/// Peaks of a sound as interleaved `[min, max, min, max, ...]` pairs,
+/// scaled to the `i8` range. `file_hash` is the hash of the audio
+/// they were computed from, so a stale cache can be detected.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Waveform {
+    pub file_hash: String,
+    pub peaks: Vec<i8>,
+}
+
+/// The waveform is cached as `{file_name}.peaks.json` next to the audio file.
+pub fn waveform_path(audio_folder_path: &Path, file_name: &str) -> PathBuf {
+    audio_folder_path.join(format!("{}.peaks.json", file_name))
+}
+
+async fn compute_peaks(audio_path: &Path) -> Result<Vec<i8>, Error> {
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-i"])
+        .arg(audio_path)
+        .args([
+            "-vn",
+            "-ac",
+            "1",
+            "-ar",
+            WAVEFORM_SAMPLE_RATE,
+            "-f",
+            "s16le",
+            "-",
+        ])
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    let samples = output
+        .stdout
+        .chunks_exact(2)
+        .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+        .collect::<Vec<i16>>();
+
+    Ok(bucket_peaks(&samples))
+}
+
+/// Splits the samples into `WAVEFORM_LENGTH` buckets and keeps the
+/// min and max of each. Clips with fewer samples than buckets
+/// repeat their samples, silent buckets are left at 0.
+fn bucket_peaks(samples: &[i16]) -> Vec<i8> {
+    let mut peaks = Vec::with_capacity(WAVEFORM_LENGTH * 2);
+
+    for index in 0..WAVEFORM_LENGTH {
+        let start = index * samples.len() / WAVEFORM_LENGTH;
+        let end = ((index + 1) * samples.len() / WAVEFORM_LENGTH).max(start + 1);
+        let bucket = samples.get(start..end.min(samples.len())).unwrap_or(&[]);
+
+        let min = bucket.iter().copied().min().unwrap_or(0);
+        let max = bucket.iter().copied().max().unwrap_or(0);
+
+        peaks.push((min >> 8) as i8);
+        peaks.push((max >> 8) as i8);
+    }
+
+    peaks
+}
+
+/// Computes the waveform of an audio file and writes it to the cache.
+pub async fn generate_waveform(
+    audio_path: &Path,
+    file_hash: &str,
+    waveform_path: &Path,
+) -> Result<Waveform, Error> {
+    let waveform = Waveform {
+        file_hash: file_hash.to_string(),
+        peaks: compute_peaks(audio_path).await?,
+    };
+
+    let content = serde_json::to_vec(&waveform)
+        .map_err(|reason| Error::new(ErrorKind::InvalidData, reason))?;
+    tokio::fs::write(waveform_path, content).await?;
+
+    Ok(waveform)
+}
+
+/// Reads the cached waveform of a sound, regenerating it
+/// when it's missing or was computed from another audio file.
+pub async fn load_waveform(sound: &Sound, audio_folder_path: &Path) -> Result<Waveform, Error> {
+    let waveform_path = waveform_path(audio_folder_path, &sound.file_name);
+
+    let cached = match tokio::fs::read(&waveform_path).await {
+        Ok(content) => serde_json::from_slice::<Waveform>(&content).ok(),
+        Err(reason) if reason.kind() == ErrorKind::NotFound => None,
+        Err(reason) => return Err(reason),
+    };
+
+    match cached {
+        Some(waveform) if waveform.file_hash == sound.file_hash => Ok(waveform),
+        _ => {
+            let mut audio_path = audio_folder_path.join(&sound.file_name);
+            audio_path.set_extension(&sound.extension);
+
+            generate_waveform(&audio_path, &sound.file_hash, &waveform_path).await
+        }
+    }
+}
+
+pub async fn remove_waveform(audio_folder_path: &Path, file_name: &str) -> Result<(), Error> {
+    match tokio::fs::remove_file(waveform_path(audio_folder_path, file_name)).await {
+        Err(reason) if reason.kind() != ErrorKind::NotFound => Err(reason),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{self, TestFolder};
+
+    fn pair(peaks: &[i8], index: usize) -> (i8, i8) {
+        (peaks[index * 2], peaks[index * 2 + 1])
+    }
+
+    #[test]
+    fn samples_that_dont_divide_evenly_are_all_bucketed() {
+        let mut samples = vec![0i16; 1000];
+        samples[0] = -100 << 8;
+        samples[999] = 100 << 8;
+
+        let peaks = bucket_peaks(&samples);
+
+        assert_eq!(peaks.len(), WAVEFORM_LENGTH * 2);
+        assert_eq!(pair(&peaks, 0), (-100, 0));
+        assert_eq!(pair(&peaks, WAVEFORM_LENGTH - 1), (0, 100));
+        assert!((1..WAVEFORM_LENGTH - 1).all(|index| pair(&peaks, index) == (0, 0)));
+    }
+
+    #[test]
+    fn short_clips_fill_every_bucket() {
+        let peaks = bucket_peaks(&[10 << 8, -20 << 8, 30 << 8]);
+
+        assert_eq!(peaks.len(), WAVEFORM_LENGTH * 2);
+        assert_eq!(pair(&peaks, 0), (10, 10));
+        assert_eq!(pair(&peaks, WAVEFORM_LENGTH / 2), (-20, -20));
+        assert_eq!(pair(&peaks, WAVEFORM_LENGTH - 1), (30, 30));
+
+        assert_eq!(bucket_peaks(&[]), vec![0; WAVEFORM_LENGTH * 2]);
+    }
+
+    #[test]
+    fn peaks_stay_in_the_i8_range() {
+        let peaks = bucket_peaks(&[i16::MIN, i16::MAX, -1, 255, 256]);
+
+        assert_eq!(pair(&peaks, 0), (-128, -128));
+        assert_eq!(pair(&peaks, 60), (127, 127));
+        assert_eq!(pair(&peaks, 110), (-1, -1));
+        assert_eq!(pair(&peaks, 160), (0, 0));
+        assert_eq!(pair(&peaks, WAVEFORM_LENGTH - 1), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn cached_waveforms_are_used_while_the_hash_matches() {
+        let folder = TestFolder::new();
+        let sound = test_utils::sound("clip");
+        let cached = Waveform {
+            file_hash: sound.file_hash.clone(),
+            peaks: vec![-1, 1],
+        };
+        tokio::fs::write(
+            waveform_path(&folder.0, &sound.file_name),
+            serde_json::to_vec(&cached).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let waveform = load_waveform(&sound, &folder.0).await.unwrap();
+        assert_eq!(waveform.peaks, cached.peaks);
+
+        // The audio file is missing, so only a regeneration can fail
+        let replaced = Sound {
+            file_hash: "another hash".to_string(),
+            ..sound
+        };
+        assert!(load_waveform(&replaced, &folder.0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn unreadable_caches_are_regenerated() {
+        let folder = TestFolder::new();
+        let sound = test_utils::sound("clip");
+        tokio::fs::write(waveform_path(&folder.0, &sound.file_name), b"{\"peaks\":")
+            .await
+            .unwrap();
+
+        assert!(load_waveform(&sound, &folder.0).await.is_err());
+    }
+}
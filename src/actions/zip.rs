@@ -0,0 +1,378 @@
+use std::io::{self, Error, ErrorKind, Read, Write};
+
+use crc32fast::Hasher;
+
+const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x0807_4b50;
+const CENTRAL_DIRECTORY_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_EXTRA_FIELD_ID: u16 = 0x0001;
+
+const VERSION: u16 = 20;
+const ZIP64_VERSION: u16 = 45;
+const STORED: u16 = 0;
+/// Bit 3 defers crc and sizes to a data descriptor after the
+/// entry content, bit 11 marks entry names as utf-8.
+const FLAGS: u16 = 0x0808;
+/// 1980-01-01, the earliest date a ZIP archive can represent.
+const DOS_DATE: u16 = (1 << 5) | 1;
+const DOS_TIME: u16 = 0;
+
+/// Values that don't fit the plain ZIP fields are moved to Zip64 records,
+/// leaving these markers in their place.
+const ZIP64_U16_MARKER: u16 = u16::MAX;
+const ZIP64_U32_MARKER: u32 = u32::MAX;
+
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+struct ZipEntry {
+    name: String,
+    crc: u32,
+    size: u64,
+    offset: u64,
+}
+
+/// Writes an uncompressed ZIP archive front to back, without ever seeking,
+/// so it can be sent to a client while the entries are still being read.
+///
+/// Sizes and crcs follow each entry in a data descriptor. Entries announced
+/// as larger than 4GiB get Zip64 sizes, and the central directory moves
+/// offsets and counts past the plain ZIP limits to Zip64 records.
+pub struct ZipStreamWriter<W: Write> {
+    inner: W,
+    entries: Vec<ZipEntry>,
+    offset: u64,
+}
+
+impl<W: Write> ZipStreamWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            entries: vec![],
+            offset: 0,
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.inner.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Adds an entry holding everything `content` reads. `size` is the expected
+    /// length of the content, used to pick between plain and Zip64 sizes.
+    pub fn write_entry(
+        &mut self,
+        name: &str,
+        size: u64,
+        content: &mut impl Read,
+    ) -> io::Result<()> {
+        let is_zip64 = size >= u64::from(ZIP64_U32_MARKER);
+        let offset = self.offset;
+
+        let mut header = Vec::with_capacity(50 + name.len());
+        put_u32(&mut header, LOCAL_FILE_HEADER_SIGNATURE);
+        put_u16(&mut header, if is_zip64 { ZIP64_VERSION } else { VERSION });
+        put_u16(&mut header, FLAGS);
+        put_u16(&mut header, STORED);
+        put_u16(&mut header, DOS_TIME);
+        put_u16(&mut header, DOS_DATE);
+        put_u32(&mut header, 0);
+
+        // The Zip64 extra field tells readers the data descriptor holds 8 byte sizes
+        if is_zip64 {
+            put_u32(&mut header, ZIP64_U32_MARKER);
+            put_u32(&mut header, ZIP64_U32_MARKER);
+            put_u16(&mut header, name.len() as u16);
+            put_u16(&mut header, 20);
+            header.extend_from_slice(name.as_bytes());
+            put_u16(&mut header, ZIP64_EXTRA_FIELD_ID);
+            put_u16(&mut header, 16);
+            put_u64(&mut header, 0);
+            put_u64(&mut header, 0);
+        } else {
+            put_u32(&mut header, 0);
+            put_u32(&mut header, 0);
+            put_u16(&mut header, name.len() as u16);
+            put_u16(&mut header, 0);
+            header.extend_from_slice(name.as_bytes());
+        }
+
+        self.write_bytes(&header)?;
+
+        let mut hasher = Hasher::new();
+        let mut written = 0u64;
+        let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+
+        loop {
+            let read = match content.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(reason) if reason.kind() == ErrorKind::Interrupted => continue,
+                Err(reason) => return Err(reason),
+            };
+
+            hasher.update(&buffer[..read]);
+            written += read as u64;
+            self.write_bytes(&buffer[..read])?;
+        }
+
+        if !is_zip64 && written >= u64::from(ZIP64_U32_MARKER) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} grew past 4GiB while being archived.", name),
+            ));
+        }
+
+        let crc = hasher.finalize();
+        let mut descriptor = Vec::with_capacity(24);
+        put_u32(&mut descriptor, DATA_DESCRIPTOR_SIGNATURE);
+        put_u32(&mut descriptor, crc);
+
+        if is_zip64 {
+            put_u64(&mut descriptor, written);
+            put_u64(&mut descriptor, written);
+        } else {
+            put_u32(&mut descriptor, written as u32);
+            put_u32(&mut descriptor, written as u32);
+        }
+
+        self.write_bytes(&descriptor)?;
+        self.entries.push(ZipEntry {
+            name: name.to_string(),
+            crc,
+            size: written,
+            offset,
+        });
+
+        Ok(())
+    }
+
+    /// Writes the central directory, which ends the archive,
+    /// and hands back the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let directory_offset = self.offset;
+        let mut directory = Vec::new();
+
+        for entry in self.entries.iter() {
+            let mut extra = Vec::new();
+
+            let size = if entry.size >= u64::from(ZIP64_U32_MARKER) {
+                put_u64(&mut extra, entry.size);
+                put_u64(&mut extra, entry.size);
+                ZIP64_U32_MARKER
+            } else {
+                entry.size as u32
+            };
+
+            let offset = if entry.offset >= u64::from(ZIP64_U32_MARKER) {
+                put_u64(&mut extra, entry.offset);
+                ZIP64_U32_MARKER
+            } else {
+                entry.offset as u32
+            };
+
+            let version = if extra.is_empty() {
+                VERSION
+            } else {
+                ZIP64_VERSION
+            };
+
+            put_u32(&mut directory, CENTRAL_DIRECTORY_HEADER_SIGNATURE);
+            put_u16(&mut directory, version);
+            put_u16(&mut directory, version);
+            put_u16(&mut directory, FLAGS);
+            put_u16(&mut directory, STORED);
+            put_u16(&mut directory, DOS_TIME);
+            put_u16(&mut directory, DOS_DATE);
+            put_u32(&mut directory, entry.crc);
+            put_u32(&mut directory, size);
+            put_u32(&mut directory, size);
+            put_u16(&mut directory, entry.name.len() as u16);
+            put_u16(
+                &mut directory,
+                if extra.is_empty() {
+                    0
+                } else {
+                    extra.len() as u16 + 4
+                },
+            );
+            put_u16(&mut directory, 0);
+            put_u16(&mut directory, 0);
+            put_u16(&mut directory, 0);
+            put_u32(&mut directory, 0);
+            put_u32(&mut directory, offset);
+            directory.extend_from_slice(entry.name.as_bytes());
+
+            if !extra.is_empty() {
+                put_u16(&mut directory, ZIP64_EXTRA_FIELD_ID);
+                put_u16(&mut directory, extra.len() as u16);
+                directory.extend_from_slice(&extra);
+            }
+        }
+
+        let entry_count = self.entries.len() as u64;
+        let directory_size = directory.len() as u64;
+        let is_zip64 = entry_count >= u64::from(ZIP64_U16_MARKER)
+            || directory_size >= u64::from(ZIP64_U32_MARKER)
+            || directory_offset >= u64::from(ZIP64_U32_MARKER);
+
+        if is_zip64 {
+            let zip64_end_offset = directory_offset + directory_size;
+
+            put_u32(&mut directory, ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+            put_u64(&mut directory, 44);
+            put_u16(&mut directory, ZIP64_VERSION);
+            put_u16(&mut directory, ZIP64_VERSION);
+            put_u32(&mut directory, 0);
+            put_u32(&mut directory, 0);
+            put_u64(&mut directory, entry_count);
+            put_u64(&mut directory, entry_count);
+            put_u64(&mut directory, directory_size);
+            put_u64(&mut directory, directory_offset);
+
+            put_u32(
+                &mut directory,
+                ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE,
+            );
+            put_u32(&mut directory, 0);
+            put_u64(&mut directory, zip64_end_offset);
+            put_u32(&mut directory, 1);
+        }
+
+        let entry_count = entry_count.min(u64::from(ZIP64_U16_MARKER)) as u16;
+        put_u32(&mut directory, END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+        put_u16(&mut directory, 0);
+        put_u16(&mut directory, 0);
+        put_u16(&mut directory, entry_count);
+        put_u16(&mut directory, entry_count);
+        put_u32(
+            &mut directory,
+            directory_size.min(u64::from(ZIP64_U32_MARKER)) as u32,
+        );
+        put_u32(
+            &mut directory,
+            directory_offset.min(u64::from(ZIP64_U32_MARKER)) as u32,
+        );
+        put_u16(&mut directory, 0);
+
+        self.write_bytes(&directory)?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+}
+
+fn put_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buffer: &mut Vec<u8>, value: u64) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use zip::ZipArchive;
+
+    use super::*;
+
+    fn read_u16(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([bytes[at], bytes[at + 1]])
+    }
+
+    fn read_u32(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    fn read_u64(bytes: &[u8], at: usize) -> u64 {
+        let mut value = [0u8; 8];
+        value.copy_from_slice(&bytes[at..at + 8]);
+        u64::from_le_bytes(value)
+    }
+
+    fn find(bytes: &[u8], signature: u32) -> Option<usize> {
+        bytes
+            .windows(4)
+            .position(|window| window == signature.to_le_bytes())
+    }
+
+    #[test]
+    fn entries_round_trip_through_a_zip_reader() {
+        let mut writer = ZipStreamWriter::new(vec![]);
+        writer
+            .write_entry("first.mp3", 5, &mut &b"first"[..])
+            .unwrap();
+        // Announced as bigger than 4GiB, so written with Zip64 sizes
+        writer
+            .write_entry("é.mp3", u64::from(u32::MAX) + 1, &mut &b"second"[..])
+            .unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut contents = vec![];
+
+        for index in 0..archive.len() {
+            let mut entry = archive.by_index(index).unwrap();
+            let mut content = String::new();
+            entry.read_to_string(&mut content).unwrap();
+            contents.push((entry.name().to_string(), content));
+        }
+
+        assert_eq!(
+            contents,
+            vec![
+                ("first.mp3".to_string(), "first".to_string()),
+                ("é.mp3".to_string(), "second".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn large_entries_and_offsets_move_to_zip64_records() {
+        let size = 5 * 1024 * 1024 * 1024;
+        let offset = 6 * 1024 * 1024 * 1024;
+        let mut writer = ZipStreamWriter::new(vec![]);
+        writer.entries.push(ZipEntry {
+            name: "large.wav".to_string(),
+            crc: 0,
+            size,
+            offset,
+        });
+        writer.offset = offset + size;
+
+        let bytes = writer.finish().unwrap();
+
+        // Sizes and offset are markers pointing to the Zip64 extra field
+        assert_eq!(read_u32(&bytes, 20), u32::MAX);
+        assert_eq!(read_u32(&bytes, 42), u32::MAX);
+        let extra = 46 + "large.wav".len();
+        assert_eq!(read_u16(&bytes, extra), ZIP64_EXTRA_FIELD_ID);
+        assert_eq!(read_u64(&bytes, extra + 4), size);
+        assert_eq!(read_u64(&bytes, extra + 20), offset);
+
+        let zip64_end = find(&bytes, ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE).unwrap();
+        assert_eq!(read_u64(&bytes, zip64_end + 48), offset + size);
+        assert!(find(&bytes, ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE).is_some());
+
+        let end = find(&bytes, END_OF_CENTRAL_DIRECTORY_SIGNATURE).unwrap();
+        assert_eq!(read_u32(&bytes, end + 16), u32::MAX);
+    }
+
+    #[test]
+    fn small_archives_have_no_zip64_records() {
+        let mut writer = ZipStreamWriter::new(vec![]);
+        writer.write_entry("sound.mp3", 1, &mut &b"x"[..]).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        assert!(find(&bytes, ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE).is_none());
+    }
+}
@@ -1,6 +1,9 @@
+use std::str::FromStr;
+
 use actix::Addr;
 use diesel::{
-    r2d2::{ConnectionManager, Pool},
+    connection::SimpleConnection,
+    r2d2::{self, ConnectionManager, CustomizeConnection, Pool},
     SqliteConnection,
 };
 use teloxide::prelude::*;
@@ -9,13 +12,85 @@ use crate::{discord::actor::DiscordActor, lock::lock_actor::SoundLockActor};
 
 pub type DatabasePool = Pool<ConnectionManager<SqliteConnection>>;
 
+/// SQLite only enforces foreign keys (and therefore `ON DELETE CASCADE`)
+/// for connections that explicitly enable it, so every pooled connection
+/// turns the pragma on when it is acquired.
+#[derive(Debug)]
+pub struct SqliteConnectionCustomizer;
+
+impl CustomizeConnection<SqliteConnection, r2d2::Error> for SqliteConnectionCustomizer {
+    fn on_acquire(&self, connection: &mut SqliteConnection) -> Result<(), r2d2::Error> {
+        connection
+            .batch_execute("PRAGMA foreign_keys = ON;")
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
+pub const DEFAULT_UPLOAD_MAX_FILE_BYTES: u64 = 20 * 1024 * 1024;
+pub const DEFAULT_UPLOAD_MAX_FILES: usize = 50;
+pub const DEFAULT_UPLOAD_MAX_DURATION_SECONDS: f64 = 120.0;
+pub const DEFAULT_IMPORT_MAX_ARCHIVE_BYTES: u64 = 1024 * 1024 * 1024;
+pub const DEFAULT_IMPORT_MAX_FILES: usize = 5000;
+pub const DEFAULT_LOUDNESS_TARGET_LUFS: f64 = -16.0;
+pub const DEFAULT_SILENCE_THRESHOLD_DB: f64 = -50.0;
+pub const DEFAULT_DUPLICATE_POLICY: DuplicatePolicy = DuplicatePolicy::Warn;
+
+/// Limits enforced on every sound sent to `POST /upload`, `POST /import`
+/// and `POST /import-url`. `max_files` only applies to `POST /upload`.
+#[derive(Clone, Copy, Debug)]
+pub struct UploadLimits {
+    pub max_file_bytes: u64,
+    pub max_files: usize,
+    pub max_duration_seconds: f64,
+}
+
+/// Limits enforced on every `POST /import` request, on top
+/// of the size and duration limits of each sound.
+#[derive(Clone, Copy, Debug)]
+pub struct ImportLimits {
+    pub max_archive_bytes: u64,
+    pub max_files: usize,
+}
+
+/// What happens to uploads that sound like a sound already in the library.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DuplicatePolicy {
+    /// Near duplicates aren't looked for
+    Off,
+    /// Near duplicates are stored, reporting the sound they match
+    Warn,
+    /// Near duplicates are refused
+    Reject,
+}
+
+impl FromStr for DuplicatePolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "off" => Ok(DuplicatePolicy::Off),
+            "warn" => Ok(DuplicatePolicy::Warn),
+            "reject" => Ok(DuplicatePolicy::Reject),
+            _ => Err(format!("Unknown duplicate policy: {}", value)),
+        }
+    }
+}
+
 pub struct AppState {
     pub app_name: String,
     pub discord_actor_addr: Addr<DiscordActor>,
     pub sound_lock_actor_addr: Addr<SoundLockActor>,
-    pub discord_guild_id: u64,
+    /// Guild sounds are played in when a request doesn't name one
+    pub default_discord_guild_id: Option<u64>,
     pub database_pool: DatabasePool,
     pub audio_folder_path: String,
+    pub upload_limits: UploadLimits,
+    pub import_limits: ImportLimits,
+    /// Loudness every sound is brought to when played on Discord
+    pub loudness_target_lufs: f64,
+    /// Volume below which audio is stripped when uploads ask for silence trimming
+    pub silence_threshold_db: f64,
+    pub duplicate_policy: DuplicatePolicy,
     pub telegram_bot: Bot,
     pub telegram_chat_id: String,
 }
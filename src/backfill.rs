@@ -0,0 +1,296 @@
+use std::{collections::HashSet, error::Error, path::Path};
+
+use actix_web::web;
+use diesel::SqliteConnection;
+use log::{error, info};
+
+use crate::{
+    actions::{
+        audio::{
+            loudness_gain, measure_loudness, playback_file_name, probe_metadata,
+            telegram_rendition_path, transcode_mp3, transcode_playback, Loudness,
+            PLAYBACK_EXTENSION,
+        },
+        fingerprint::{compute_fingerprint, encode_fingerprint},
+        sounds::{
+            fetch_sounds, fetch_unfingerprinted_sound_ids, set_sound_fingerprint,
+            set_sound_loudness, set_sound_metadata, set_sound_playback_file_name,
+        },
+    },
+    app_state::DatabasePool,
+    models::Sound,
+};
+
+/// Runs the ingest steps added after a sound was uploaded on the
+/// existing library. Sounds that were already processed are skipped,
+/// so it's safe to run it again after a failure.
+pub async fn run_backfill(
+    database_pool: DatabasePool,
+    audio_folder_path: &Path,
+    loudness_target_lufs: f64,
+) -> Result<(), Box<dyn Error>> {
+    let database_pool_clone = database_pool.clone();
+    let (sounds, unfingerprinted_sound_ids) = web::block(move || {
+        let database_connection = database_pool_clone
+            .get()
+            .expect("couldn't get db connection from pool");
+
+        // Sounds are loaded without their fingerprint
+        Ok::<_, diesel::result::Error>((
+            fetch_sounds(&database_connection)?,
+            fetch_unfingerprinted_sound_ids(&database_connection)?
+                .into_iter()
+                .collect::<HashSet<String>>(),
+        ))
+    })
+    .await??;
+
+    info!("Backfilling {} sounds", sounds.len());
+
+    let mut incomplete_sound_count = 0;
+
+    for sound in sounds {
+        let needs_fingerprint = unfingerprinted_sound_ids.contains(&sound.id);
+
+        let failed_steps = backfill_sound(
+            &sound,
+            needs_fingerprint,
+            database_pool.clone(),
+            audio_folder_path,
+            loudness_target_lufs,
+        )
+        .await;
+
+        if !failed_steps.is_empty() {
+            error!(
+                "Failed to backfill {} of sound with id {}",
+                failed_steps.join(", "),
+                sound.id
+            );
+            incomplete_sound_count += 1;
+        }
+    }
+
+    info!(
+        "Backfill finished, {} sounds have steps left to retry",
+        incomplete_sound_count
+    );
+
+    Ok(())
+}
+
+type StepResult<T> = Result<T, Box<dyn Error>>;
+
+/// Runs every step a sound still needs and returns the ones that failed.
+///
+/// Steps don't depend on each other succeeding, so a failure is
+/// logged and the remaining steps still run.
+async fn backfill_sound(
+    sound: &Sound,
+    needs_fingerprint: bool,
+    database_pool: DatabasePool,
+    audio_folder_path: &Path,
+    loudness_target_lufs: f64,
+) -> Vec<&'static str> {
+    let mut audio_path = audio_folder_path.join(&sound.file_name);
+    audio_path.set_extension(&sound.extension);
+    let mut failed_steps = vec![];
+
+    let loudness = if sound.loudness_lufs.is_none() || sound.true_peak_dbtp.is_none() {
+        let result = backfill_loudness(sound, &audio_path, database_pool.clone()).await;
+        check_step("loudness", sound, result, &mut failed_steps)
+    } else {
+        None
+    };
+
+    // The rendition is encoded with the loudness gain, so it's redone once the loudness is known
+    if sound.playback_file_name.is_none() || loudness.is_some() {
+        let gain = loudness
+            .map(|loudness| (loudness.integrated_lufs, Some(loudness.true_peak_dbtp)))
+            .or_else(|| {
+                sound
+                    .loudness_lufs
+                    .map(|loudness_lufs| (loudness_lufs, sound.true_peak_dbtp))
+            })
+            .map_or(1.0, |(loudness_lufs, true_peak_dbtp)| {
+                loudness_gain(loudness_lufs, true_peak_dbtp, loudness_target_lufs)
+            });
+
+        let result = backfill_playback_rendition(
+            sound,
+            &audio_path,
+            gain,
+            database_pool.clone(),
+            audio_folder_path,
+        )
+        .await;
+        check_step("playback rendition", sound, result, &mut failed_steps);
+    }
+
+    if let Some(telegram_path) =
+        telegram_rendition_path(audio_folder_path, &sound.file_name, &sound.extension)
+    {
+        if !telegram_path.exists() {
+            let result = transcode_mp3(&audio_path, &telegram_path).await;
+            if result.is_err() {
+                let _ = tokio::fs::remove_file(&telegram_path).await;
+            }
+            check_step(
+                "telegram rendition",
+                sound,
+                result.map_err(Into::into),
+                &mut failed_steps,
+            );
+        }
+    }
+
+    if sound.duration_ms.is_none() {
+        let result = backfill_metadata(sound, &audio_path, database_pool.clone()).await;
+        check_step("metadata", sound, result, &mut failed_steps);
+    }
+
+    if needs_fingerprint {
+        let result = backfill_fingerprint(sound, &audio_path, database_pool).await;
+        check_step("fingerprint", sound, result, &mut failed_steps);
+    }
+
+    failed_steps
+}
+
+/// Logs a failed step and adds it to the ones the sound failed.
+fn check_step<T>(
+    step: &'static str,
+    sound: &Sound,
+    result: StepResult<T>,
+    failed_steps: &mut Vec<&'static str>,
+) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(reason) => {
+            error!(
+                "Failed to backfill {} of sound with id {}. Reason: {:?}",
+                step, sound.id, reason
+            );
+            failed_steps.push(step);
+            None
+        }
+    }
+}
+
+/// Stores a step's result through `update`, on the blocking thread pool.
+async fn store<F>(database_pool: DatabasePool, update: F) -> StepResult<()>
+where
+    F: FnOnce(&SqliteConnection) -> Result<usize, diesel::result::Error> + Send + 'static,
+{
+    web::block(move || {
+        let database_connection = database_pool
+            .get()
+            .expect("couldn't get db connection from pool");
+
+        update(&database_connection)
+    })
+    .await??;
+
+    Ok(())
+}
+
+async fn backfill_loudness(
+    sound: &Sound,
+    audio_path: &Path,
+    database_pool: DatabasePool,
+) -> StepResult<Loudness> {
+    let loudness = measure_loudness(audio_path).await?;
+
+    let sound_id = sound.id.clone();
+    store(database_pool, move |database_connection| {
+        set_sound_loudness(sound_id, loudness, database_connection)
+    })
+    .await?;
+
+    Ok(loudness)
+}
+
+async fn backfill_playback_rendition(
+    sound: &Sound,
+    audio_path: &Path,
+    gain: f32,
+    database_pool: DatabasePool,
+    audio_folder_path: &Path,
+) -> StepResult<()> {
+    let playback_file_name = playback_file_name(&sound.file_name);
+    let mut playback_path = audio_folder_path.join(&playback_file_name);
+    playback_path.set_extension(PLAYBACK_EXTENSION);
+
+    if let Err(reason) = transcode_playback(audio_path, &playback_path, gain).await {
+        let _ = tokio::fs::remove_file(&playback_path).await;
+        return Err(reason.into());
+    }
+
+    let sound_id = sound.id.clone();
+    store(database_pool, move |database_connection| {
+        set_sound_playback_file_name(sound_id, playback_file_name, database_connection)
+    })
+    .await
+}
+
+async fn backfill_metadata(
+    sound: &Sound,
+    audio_path: &Path,
+    database_pool: DatabasePool,
+) -> StepResult<()> {
+    let metadata = probe_metadata(audio_path).await?;
+
+    let sound_id = sound.id.clone();
+    store(database_pool, move |database_connection| {
+        set_sound_metadata(sound_id, metadata, database_connection)
+    })
+    .await
+}
+
+async fn backfill_fingerprint(
+    sound: &Sound,
+    audio_path: &Path,
+    database_pool: DatabasePool,
+) -> StepResult<()> {
+    let fingerprint = encode_fingerprint(&compute_fingerprint(audio_path).await?);
+
+    let sound_id = sound.id.clone();
+    store(database_pool, move |database_connection| {
+        set_sound_fingerprint(sound_id, fingerprint, database_connection)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::r2d2::{ConnectionManager, Pool};
+
+    use super::*;
+    use crate::test_utils::{self, TestFolder};
+
+    #[tokio::test]
+    async fn failed_steps_dont_stop_the_next_ones() {
+        let folder = TestFolder::new();
+        let database_pool = Pool::builder()
+            .max_size(1)
+            .build(ConnectionManager::<SqliteConnection>::new(":memory:"))
+            .unwrap();
+        let sound = Sound {
+            extension: "wav".to_string(),
+            ..test_utils::sound("missing")
+        };
+
+        let failed_steps = backfill_sound(&sound, true, database_pool, &folder.0, -16.0).await;
+
+        assert_eq!(
+            failed_steps,
+            [
+                "loudness",
+                "playback rendition",
+                "telegram rendition",
+                "metadata",
+                "fingerprint"
+            ]
+        );
+    }
+}
@@ -6,9 +6,20 @@ use serenity::{
     async_trait,
     client::{Context, EventHandler},
     model::{event::ResumedEvent, gateway::Ready},
+    prelude::TypeMapKey,
 };
+
+use crate::app_state::DatabasePool;
+
 pub struct DiscordHandler;
 
+/// Makes the database pool available to bot commands through the client data.
+pub struct DatabasePoolKey;
+
+impl TypeMapKey for DatabasePoolKey {
+    type Value = DatabasePool;
+}
+
 #[async_trait]
 impl EventHandler for DiscordHandler {
     async fn ready(&self, _ctx: Context, ready: Ready) {
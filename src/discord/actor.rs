@@ -1,20 +1,32 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use crate::{
     lock::{
         lock_actor::SoundLockActor,
-        messages::{GetLockStatus, Lock, Unlock},
+        messages::{LockError, TryLock, Unlock},
     },
     models::Sound,
 };
 use actix::prelude::*;
 use actix_broker::{Broker, SystemBroker};
-use log::info;
-use serenity::{async_trait, model::prelude::GuildId};
+use log::{error, info};
+use serenity::{
+    async_trait,
+    cache::Cache,
+    model::{
+        channel::ChannelType,
+        prelude::{ChannelId, GuildId},
+    },
+    prelude::Mutex,
+};
 use songbird::{
     driver::Bitrate,
-    input::{self, cached::Compressed},
-    Event, EventContext, EventHandler as VoiceEventHandler, Songbird, TrackEvent,
+    input::{self, cached::Compressed, Input},
+    Call, Event, EventContext, EventHandler as VoiceEventHandler, Songbird, TrackEvent,
 };
 
 struct SongEndNotifier {}
@@ -27,17 +39,173 @@ impl VoiceEventHandler for SongEndNotifier {
     }
 }
 
+/// Opens a sound for playback, preferring the pre-encoded rendition
+/// and falling back to compressing the original file.
+///
+/// Returns the volume to play the source at: the rendition already has
+/// the loudness gain applied, the original file is played at `volume`.
+async fn open_source(
+    audio_path: &Path,
+    playback_path: Option<PathBuf>,
+    volume: f32,
+) -> Result<(Input, f32), input::error::Error> {
+    /*
+     * The rendition already holds Opus frames, so no ffmpeg
+     * process is spawned. Songbird sends the frames as they
+     * are when the volume is left at 1.0, and only decodes
+     * them to apply any other volume.
+     */
+    if let Some(playback_path) = playback_path {
+        match input::dca(&playback_path).await {
+            Ok(sound_src) => return Ok((sound_src, 1.0)),
+            Err(reason) => error!(
+                "Failed to open playback rendition at {:?}. Reason: {:?}",
+                playback_path, reason
+            ),
+        }
+    }
+
+    let bitrate = Bitrate::BitsPerSecond(128_000);
+    let audio_source = input::ffmpeg(audio_path).await?;
+
+    Ok((Compressed::new(audio_source, bitrate)?.into(), volume))
+}
+
+/// Why the bot couldn't join, leave or play in a voice channel.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VoiceError {
+    /// The bot isn't in a voice channel of the guild
+    NotConnected,
+    /// Another sound is still playing
+    Locked,
+    /// The sound is being deleted or edited
+    SoundBusy,
+    /// The audio file of the sound is gone, it was most likely deleted
+    MissingAudio,
+    /// The bot isn't in the guild
+    UnknownGuild,
+    /// The guild has no voice channel with that id
+    UnknownChannel,
+    Failed(String),
+}
+
+impl fmt::Display for VoiceError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VoiceError::NotConnected => write!(formatter, "Bot is not in a voice channel"),
+            VoiceError::Locked => write!(formatter, "Another sound is playing"),
+            VoiceError::SoundBusy => write!(formatter, "Sound is being changed"),
+            VoiceError::MissingAudio => write!(formatter, "Audio of the sound is missing"),
+            VoiceError::UnknownGuild => write!(formatter, "Bot is not in that guild"),
+            VoiceError::UnknownChannel => {
+                write!(formatter, "Guild has no voice channel with that id")
+            }
+            VoiceError::Failed(reason) => write!(formatter, "{}", reason),
+        }
+    }
+}
+
+/// Joins a voice channel, unless the bot is already in it.
+async fn join_channel(
+    manager: &Songbird,
+    cache: &Cache,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> Result<Arc<Mutex<Call>>, VoiceError> {
+    let guild = cache
+        .guild(guild_id)
+        .await
+        .ok_or(VoiceError::UnknownGuild)?;
+
+    let is_voice_channel = guild
+        .channels
+        .get(&channel_id)
+        .is_some_and(|channel| channel.kind == ChannelType::Voice);
+
+    if !is_voice_channel {
+        return Err(VoiceError::UnknownChannel);
+    }
+
+    if let Some(handler_lock) = manager.get(guild_id) {
+        let current_channel = handler_lock.lock().await.current_channel();
+
+        if current_channel.map(|current_channel| current_channel.0) == Some(channel_id.0) {
+            return Ok(handler_lock);
+        }
+    }
+
+    info!(
+        "Joining voice channel {} of guild {}",
+        channel_id.0, guild_id.0
+    );
+    let (handler_lock, join_result) = manager.join(guild_id, channel_id).await;
+
+    match join_result {
+        Ok(()) => Ok(handler_lock),
+        Err(reason) => {
+            error!(
+                "Failed to join voice channel {} of guild {}. Reason: {:?}",
+                channel_id.0, guild_id.0, reason
+            );
+            Err(VoiceError::Failed(format!(
+                "Failed to join voice channel: {}",
+                reason
+            )))
+        }
+    }
+}
+
 pub struct DiscordActor {
-    pub discord_guild_id: u64,
     pub songbird: Arc<Songbird>,
+    pub cache: Arc<Cache>,
     pub sound_lock_actor_addr: Addr<SoundLockActor>,
 }
 
+#[derive(Clone, Debug)]
+pub struct VoiceChannel {
+    pub id: u64,
+    pub name: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct DiscordGuild {
+    pub id: u64,
+    pub name: String,
+    /// Sorted the way Discord lists them
+    pub voice_channels: Vec<VoiceChannel>,
+}
+
+/// Lists the guilds the bot is in, as seen by the serenity cache.
+#[derive(Clone, Message)]
+#[rtype(result = "Vec<DiscordGuild>")]
+pub struct GetGuilds {}
+
+#[derive(Clone, Message)]
+#[rtype(result = "Result<(), VoiceError>")]
+pub struct JoinVoiceChannel {
+    pub guild_id: u64,
+    pub channel_id: u64,
+}
+
+#[derive(Clone, Message)]
+#[rtype(result = "Result<(), VoiceError>")]
+pub struct LeaveVoiceChannel {
+    pub guild_id: u64,
+}
+
 /// Define message
 #[derive(Clone, Message)]
-#[rtype(result = "()")]
+#[rtype(result = "Result<(), VoiceError>")]
 pub struct PlayAudio {
+    pub guild_id: u64,
+    /// Voice channel to join when the bot isn't already in it
+    pub channel_id: Option<u64>,
     pub audio_path: PathBuf,
+    /// Pre-encoded Opus rendition of the sound, preferred over `audio_path`
+    pub playback_path: Option<PathBuf>,
+    /// Gain bringing the original file to the loudness target,
+    /// the playback rendition is encoded with it already applied
+    pub volume: f32,
     pub sound: Sound,
 }
 
@@ -54,44 +222,159 @@ impl Actor for DiscordActor {
 }
 
 impl Handler<PlayAudio> for DiscordActor {
-    type Result = ();
+    type Result = ResponseFuture<Result<(), VoiceError>>;
 
-    fn handle(&mut self, msg: PlayAudio, ctx: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: PlayAudio, _ctx: &mut Self::Context) -> Self::Result {
         let audio_path = msg.audio_path;
+        let playback_path = msg.playback_path;
+        let volume = msg.volume;
         let sound = msg.sound;
-        let guild_id: GuildId = self.discord_guild_id.into();
+        let guild_id: GuildId = msg.guild_id.into();
+        let channel_id: Option<ChannelId> = msg.channel_id.map(ChannelId::from);
         let manager = self.songbird.clone();
+        let cache = self.cache.clone();
 
         let sound_lock_actor_addr_clone = self.sound_lock_actor_addr.clone();
 
-        let future = async move {
-            let sound_lock_status = sound_lock_actor_addr_clone
-                .send(GetLockStatus {})
+        Box::pin(async move {
+            let sound_id = sound.id.clone();
+
+            /*
+             * The lock actor checks and takes the lock in a single message,
+             * so two plays can't both see it free and play over each other.
+             * Taking it also keeps the sound from being deleted or edited,
+             * so its files stay in place while it plays.
+             */
+            sound_lock_actor_addr_clone
+                .send(TryLock { sound })
                 .await
-                .unwrap();
+                .map_err(|reason| VoiceError::Failed(reason.to_string()))?
+                .map_err(|reason| match reason {
+                    LockError::Reserved => VoiceError::SoundBusy,
+                    LockError::Locked | LockError::Playing => {
+                        info!("Sound Lock is locked. Not playing audio.");
+                        VoiceError::Locked
+                    }
+                })?;
 
-            if let Some(status) = sound_lock_status {
-                if status.is_locked {
-                    info!("Sound Lock is locked. Not playing audio.");
-                    return;
+            let play_result = async {
+                if !audio_path.exists() {
+                    return Err(VoiceError::MissingAudio);
                 }
-            }
 
-            if let Some(handler_lock) = manager.get(guild_id) {
+                let handler_lock = match channel_id {
+                    Some(channel_id) => {
+                        join_channel(&manager, &cache, guild_id, channel_id).await?
+                    }
+                    None => manager.get(guild_id).ok_or(VoiceError::NotConnected)?,
+                };
+
                 info!("Playing audio");
-                let bitrate = Bitrate::BitsPerSecond(128_000);
-                let audio_source = input::ffmpeg(&audio_path).await.expect("Link may be dead.");
-                let sound_src = Compressed::new(audio_source, bitrate)
-                    .expect("ffmpeg parameters to be properly defined");
+                let (sound_src, volume) = open_source(&audio_path, playback_path, volume)
+                    .await
+                    .map_err(|reason| {
+                        error!(
+                            "Failed to open audio for sound with id {}. Reason: {:?}",
+                            sound_id, reason
+                        );
+                        VoiceError::Failed(format!("Failed to open audio: {:?}", reason))
+                    })?;
 
-                Broker::<SystemBroker>::issue_async(Lock { sound });
                 let mut handler = handler_lock.lock().await;
-                let track_handle = handler.play_only_source(sound_src.into());
+                let track_handle = handler.play_only_source(sound_src);
+                let _ = track_handle.set_volume(volume);
                 let _ = track_handle.add_event(Event::Track(TrackEvent::End), SongEndNotifier {});
+
+                Ok(())
             }
-        }
-        .into_actor(self);
+            .await;
+
+            if play_result.is_err() {
+                sound_lock_actor_addr_clone.do_send(Unlock {});
+            }
+
+            play_result
+        })
+    }
+}
+
+impl Handler<JoinVoiceChannel> for DiscordActor {
+    type Result = ResponseFuture<Result<(), VoiceError>>;
+
+    fn handle(&mut self, msg: JoinVoiceChannel, _ctx: &mut Self::Context) -> Self::Result {
+        let manager = self.songbird.clone();
+        let cache = self.cache.clone();
+
+        Box::pin(async move {
+            join_channel(&manager, &cache, msg.guild_id.into(), msg.channel_id.into())
+                .await
+                .map(|_| ())
+        })
+    }
+}
+
+impl Handler<LeaveVoiceChannel> for DiscordActor {
+    type Result = ResponseFuture<Result<(), VoiceError>>;
+
+    fn handle(&mut self, msg: LeaveVoiceChannel, _ctx: &mut Self::Context) -> Self::Result {
+        let manager = self.songbird.clone();
+        let guild_id: GuildId = msg.guild_id.into();
+
+        Box::pin(async move {
+            if manager.get(guild_id).is_none() {
+                return Err(VoiceError::NotConnected);
+            }
+
+            info!("Leaving voice channel of guild {}", guild_id.0);
+            manager.remove(guild_id).await.map_err(|reason| {
+                error!(
+                    "Failed to leave voice channel of guild {}. Reason: {:?}",
+                    guild_id.0, reason
+                );
+                VoiceError::Failed(format!("Failed to leave voice channel: {}", reason))
+            })
+        })
+    }
+}
+
+impl Handler<GetGuilds> for DiscordActor {
+    type Result = ResponseFuture<Vec<DiscordGuild>>;
+
+    fn handle(&mut self, _msg: GetGuilds, _ctx: &mut Self::Context) -> Self::Result {
+        let cache = self.cache.clone();
+
+        Box::pin(async move {
+            let mut guilds = Vec::new();
+
+            for guild_id in cache.guilds().await {
+                let guild = match cache.guild(guild_id).await {
+                    Some(guild) => guild,
+                    None => continue,
+                };
+
+                let mut channels = guild
+                    .channels
+                    .values()
+                    .filter(|channel| channel.kind == ChannelType::Voice)
+                    .collect::<Vec<_>>();
+                channels.sort_by_key(|channel| (channel.position, channel.id.0));
+
+                guilds.push(DiscordGuild {
+                    id: guild.id.0,
+                    name: guild.name.clone(),
+                    voice_channels: channels
+                        .into_iter()
+                        .map(|channel| VoiceChannel {
+                            id: channel.id.0,
+                            name: channel.name.clone(),
+                        })
+                        .collect(),
+                });
+            }
+
+            guilds.sort_by(|first, second| first.name.cmp(&second.name));
 
-        future.wait(ctx);
+            guilds
+        })
     }
 }
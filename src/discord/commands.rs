@@ -1,3 +1,7 @@
+use crate::{
+    actions::search::{search_sounds, SEARCH_RESULT_LIMIT},
+    discord::DatabasePoolKey,
+};
 use serenity::{
     client::Context,
     framework::standard::{
@@ -16,7 +20,7 @@ fn check_msg(result: SerenityResult<Message>) {
 }
 
 #[group]
-#[commands(join, leave, play, ping)]
+#[commands(join, leave, play, ping, search)]
 pub struct BotCommands;
 
 #[command]
@@ -147,3 +151,49 @@ async fn play(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
 
     Ok(())
 }
+
+#[command]
+async fn search(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let search = args.rest().to_string();
+
+    if search.trim().is_empty() {
+        check_msg(
+            msg.channel_id
+                .say(&ctx.http, "Must provide something to search for")
+                .await,
+        );
+
+        return Ok(());
+    }
+
+    let database_pool = {
+        let data = ctx.data.read().await;
+        data.get::<DatabasePoolKey>()
+            .cloned()
+            .expect("Database pool placed in at initialisation.")
+    };
+
+    let sounds = tokio::task::spawn_blocking(move || {
+        let database_connection = database_pool
+            .get()
+            .expect("couldn't get db connection from pool");
+
+        search_sounds(&search, SEARCH_RESULT_LIMIT, &database_connection)
+    })
+    .await??;
+
+    if sounds.is_empty() {
+        check_msg(msg.channel_id.say(&ctx.http, "No sounds found").await);
+        return Ok(());
+    }
+
+    let response = sounds
+        .iter()
+        .map(|sound| format!("**{}** `{}`", sound.name, sound.id))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    check_msg(msg.channel_id.say(&ctx.http, response).await);
+
+    Ok(())
+}
@@ -1,4 +1,19 @@
 pub mod add_tags;
+pub mod delete_sound;
+pub mod discord_guilds;
+pub mod discord_voice;
+pub mod download_sounds;
+pub mod duplicate_sounds;
+pub mod edit_sound;
+pub mod get_sound;
+pub mod import;
+pub mod import_url;
 pub mod play_sound;
+pub mod remove_tags;
+pub mod search_sounds;
 pub mod sounds;
+pub mod tags;
+pub mod update_sound;
 pub mod upload;
+mod voice;
+pub mod waveform;
@@ -1,10 +1,13 @@
-use crate::{actions::tags::insert_tags, app_state::AppState};
+use crate::{
+    actions::tags::insert_tags, app_state::AppState, websocket::messages::WsSoundsChanged,
+};
+use actix_broker::{Broker, SystemBroker};
 use actix_web::{
     put,
     web::{Data, Json, Path},
     Error, HttpResponse,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize)]
 pub struct AddTagsRequestPath {
@@ -17,6 +20,12 @@ pub struct AddTagsRequestBody {
     tags: Vec<String>,
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorPayload {
+    message: String,
+}
+
 #[put("/add-tags/{sound_id}")]
 pub async fn add_tags_handler(
     path: Path<AddTagsRequestPath>,
@@ -30,5 +39,13 @@ pub async fn add_tags_handler(
     )
     .await?;
 
-    Ok(HttpResponse::Ok().json(updated_sound))
+    match updated_sound {
+        Some(updated_sound) => {
+            Broker::<SystemBroker>::issue_async(WsSoundsChanged {});
+            Ok(HttpResponse::Ok().json(updated_sound))
+        }
+        None => Ok(HttpResponse::NotFound().json(ErrorPayload {
+            message: format!("Failed to find sound with id: {}", path.sound_id),
+        })),
+    }
 }
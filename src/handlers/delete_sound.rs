@@ -0,0 +1,151 @@
+use std::path::Path;
+
+use actix_broker::{Broker, SystemBroker};
+use actix_web::{
+    delete,
+    error::ErrorInternalServerError,
+    web::{self, Data},
+    Error, HttpResponse,
+};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::{
+        fs::remove_sound_file,
+        ingest::remove_derived_files,
+        sounds::{delete_sound, fetch_sound_by_id},
+    },
+    app_state::AppState,
+    lock::{lock_actor::SoundReservation, messages::LockError},
+    websocket::messages::WsSoundsChanged,
+};
+
+#[derive(Deserialize)]
+pub struct DeleteSoundRequestPath {
+    sound_id: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorPayload {
+    message: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DeleteSoundResponse {
+    sound_id: String,
+}
+
+#[delete("/sounds/{sound_id}")]
+pub async fn delete_sound_handler(
+    path: web::Path<DeleteSoundRequestPath>,
+    data: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let sound_id = path.sound_id.clone();
+
+    /*
+     * A sound that is currently being played
+     * is held by the sound lock, so it can't be
+     * removed until playback is over. The reservation
+     * keeps it from being played until it's removed.
+     */
+    let reservation =
+        SoundReservation::reserve(data.sound_lock_actor_addr.clone(), sound_id.clone())
+            .await
+            .map_err(ErrorInternalServerError)?;
+
+    let _reservation = match reservation {
+        Ok(reservation) => reservation,
+        Err(LockError::Reserved) => {
+            return Ok(HttpResponse::Conflict().json(ErrorPayload {
+                message: format!(
+                    "Sound with id {} is being changed and can't be deleted.",
+                    sound_id
+                ),
+            }));
+        }
+        Err(_) => {
+            return Ok(HttpResponse::Conflict().json(ErrorPayload {
+                message: format!(
+                    "Sound with id {} is currently playing and can't be deleted.",
+                    sound_id
+                ),
+            }));
+        }
+    };
+
+    let database_pool = data.database_pool.clone();
+    let sound_id_clone = sound_id.clone();
+    let result = web::block(move || {
+        let database_connection = database_pool
+            .get()
+            .expect("couldn't get db connection from pool");
+
+        let sound = fetch_sound_by_id(sound_id_clone.clone(), &database_connection);
+
+        if sound.is_some() {
+            delete_sound(sound_id_clone, &database_connection)?;
+        }
+
+        Ok::<_, diesel::result::Error>(sound)
+    })
+    .await?;
+
+    let sound = match result {
+        Ok(Some(sound)) => sound,
+        Ok(None) => {
+            return Ok(HttpResponse::NotFound().json(ErrorPayload {
+                message: format!("Failed to find sound with id: {}", sound_id),
+            }));
+        }
+        Err(reason) => {
+            error!("Failed to delete sound from database. Reason: {:?}", reason);
+            return Ok(HttpResponse::InternalServerError().json(ErrorPayload {
+                message: "Server failed to delete sound from database.".to_string(),
+            }));
+        }
+    };
+
+    let audio_folder_path = Path::new(&data.audio_folder_path);
+    if let Err(reason) = remove_derived_files(
+        &sound.file_name,
+        sound.playback_file_name,
+        audio_folder_path,
+    )
+    .await
+    {
+        error!(
+            "Failed to remove derived files for sound with id {}. Reason: {:?}",
+            sound_id, reason
+        );
+    }
+
+    if let Err(reason) =
+        remove_sound_file(sound.file_name, sound.extension, audio_folder_path).await
+    {
+        error!(
+            "Failed to remove audio file for sound with id {}. Reason: {:?}",
+            sound_id, reason
+        );
+    }
+
+    // Edited sounds keep the file they were edited from
+    if let (Some(original_file_name), Some(original_extension)) =
+        (sound.original_file_name, sound.original_extension)
+    {
+        if let Err(reason) =
+            remove_sound_file(original_file_name, original_extension, audio_folder_path).await
+        {
+            error!(
+                "Failed to remove original audio file for sound with id {}. Reason: {:?}",
+                sound_id, reason
+            );
+        }
+    }
+
+    Broker::<SystemBroker>::issue_async(WsSoundsChanged {});
+
+    Ok(HttpResponse::Ok().json(DeleteSoundResponse { sound_id }))
+}
@@ -0,0 +1,52 @@
+use actix_web::{error::ErrorInternalServerError, get, web::Data, Error, HttpResponse};
+use serde::Serialize;
+
+use crate::{app_state::AppState, discord::actor::GetGuilds};
+
+/// Ids are sent as strings, since Discord snowflakes
+/// don't fit in a JavaScript number.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VoiceChannelResponse {
+    id: String,
+    name: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DiscordGuildResponse {
+    id: String,
+    name: String,
+    /// Whether sounds are played in this guild when no `guildId` is sent
+    is_default: bool,
+    voice_channels: Vec<VoiceChannelResponse>,
+}
+
+/// Lists the guilds the bot is in along with their voice channels.
+#[get("/discord/guilds")]
+pub async fn discord_guilds_handler(data: Data<AppState>) -> Result<HttpResponse, Error> {
+    let guilds = data
+        .discord_actor_addr
+        .send(GetGuilds {})
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let response = guilds
+        .into_iter()
+        .map(|guild| DiscordGuildResponse {
+            id: guild.id.to_string(),
+            name: guild.name,
+            is_default: data.default_discord_guild_id == Some(guild.id),
+            voice_channels: guild
+                .voice_channels
+                .into_iter()
+                .map(|channel| VoiceChannelResponse {
+                    id: channel.id.to_string(),
+                    name: channel.name,
+                })
+                .collect(),
+        })
+        .collect::<Vec<DiscordGuildResponse>>();
+
+    Ok(HttpResponse::Ok().json(response))
+}
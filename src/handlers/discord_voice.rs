@@ -0,0 +1,79 @@
+use actix_web::{
+    error::ErrorInternalServerError,
+    post,
+    web::{Data, Json},
+    Error, HttpResponse,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    discord::actor::{JoinVoiceChannel, LeaveVoiceChannel},
+    handlers::voice::{guild_id, parse_discord_id},
+};
+
+/// Ids are strings, since Discord snowflakes don't fit in a JavaScript
+/// number. `guildId` defaults to `DISCORD_GUILD_ID`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JoinVoiceChannelPayload {
+    guild_id: Option<String>,
+    channel_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaveVoiceChannelPayload {
+    guild_id: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VoiceChannelResponse {
+    guild_id: String,
+    channel_id: Option<String>,
+}
+
+/// Makes the bot join a voice channel, moving it
+/// there if it's in another channel of the guild.
+#[post("/discord/join")]
+pub async fn join_voice_channel_handler(
+    json: Json<JoinVoiceChannelPayload>,
+    data: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let guild_id = guild_id(&json.guild_id, &data)?;
+    let channel_id = parse_discord_id(&json.channel_id, "channelId")?;
+
+    data.discord_actor_addr
+        .send(JoinVoiceChannel {
+            guild_id,
+            channel_id,
+        })
+        .await
+        .map_err(ErrorInternalServerError)??;
+
+    Ok(HttpResponse::Ok().json(VoiceChannelResponse {
+        guild_id: guild_id.to_string(),
+        channel_id: Some(channel_id.to_string()),
+    }))
+}
+
+/// Makes the bot leave the voice channel it's in, answering
+/// with a 409 when it isn't in a voice channel of the guild.
+#[post("/discord/leave")]
+pub async fn leave_voice_channel_handler(
+    json: Json<LeaveVoiceChannelPayload>,
+    data: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let guild_id = guild_id(&json.guild_id, &data)?;
+
+    data.discord_actor_addr
+        .send(LeaveVoiceChannel { guild_id })
+        .await
+        .map_err(ErrorInternalServerError)??;
+
+    Ok(HttpResponse::Ok().json(VoiceChannelResponse {
+        guild_id: guild_id.to_string(),
+        channel_id: None,
+    }))
+}
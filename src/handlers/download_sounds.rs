@@ -0,0 +1,125 @@
+use std::{
+    io::{self, BufWriter, ErrorKind, Write},
+    path::PathBuf,
+};
+
+use actix_web::{
+    get,
+    http::header,
+    web::{self, Bytes, Data, Query},
+    Error, HttpResponse,
+};
+use log::error;
+use serde::Serialize;
+use serenity::futures::stream;
+use tokio::sync::mpsc;
+
+use crate::{
+    actions::{
+        archive::write_sounds_archive,
+        pagination::{fetch_filtered_sounds, SoundsFilter},
+    },
+    app_state::AppState,
+};
+
+const ARCHIVE_FILE_NAME: &str = "muminst-sounds.zip";
+
+/// Bytes gathered before being sent as one chunk of the body.
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Chunks waiting to be sent before the archive writer is held back.
+const CHANNEL_CAPACITY: usize = 16;
+
+type ArchiveChunk = Result<Bytes, io::Error>;
+
+/// Sends everything written to it as chunks of the response body, waiting
+/// while the client is behind. Fails once the client is gone.
+struct ChannelWriter(mpsc::Sender<ArchiveChunk>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(Bytes::copy_from_slice(buffer)))
+            .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "Client stopped the download."))?;
+
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorPayload {
+    message: String,
+}
+
+/// Sends a ZIP archive of the library. Only the `q`, `tags`
+/// and `tagMatch` parameters of `GET /sounds` are taken into account.
+#[get("/download-sounds")]
+pub async fn download_sounds_handler(
+    query: Query<SoundsFilter>,
+    data: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let filter = query.into_inner();
+    let database_pool = data.database_pool.clone();
+    let result = web::block(move || {
+        let database_connection = &database_pool
+            .get()
+            .expect("couldn't get db connection from pool");
+
+        fetch_filtered_sounds(&filter, database_connection)
+    })
+    .await?;
+
+    let sounds = match result {
+        Ok(sounds) => sounds,
+        Err(reason) => {
+            error!("Failed to fetch sounds from database. Reason: {:?}", reason);
+            return Ok(HttpResponse::InternalServerError().json(ErrorPayload {
+                message: "Server failed to fetch sounds from database.".to_string(),
+            }));
+        }
+    };
+
+    /*
+     * The archive is written on a blocking thread while the body is sent,
+     * so nothing is kept on disk and the first bytes leave right away.
+     */
+    let audio_folder_path = PathBuf::from(&data.audio_folder_path);
+    let (sender, receiver) = mpsc::channel::<ArchiveChunk>(CHANNEL_CAPACITY);
+    let error_sender = sender.clone();
+
+    actix_web::rt::spawn(async move {
+        let result = web::block(move || {
+            let writer = BufWriter::with_capacity(CHUNK_SIZE, ChannelWriter(sender));
+
+            write_sounds_archive(sounds, &audio_folder_path, writer)
+        })
+        .await;
+
+        let reason = match result {
+            Ok(Ok(())) => return,
+            Ok(Err(reason)) if reason.kind() == ErrorKind::BrokenPipe => return,
+            Ok(Err(reason)) => reason,
+            Err(reason) => io::Error::other(reason.to_string()),
+        };
+
+        // Failing the body cuts the download short instead of ending it as a valid archive
+        error!("Failed to stream sounds archive. Reason: {:?}", reason);
+        let _ = error_sender.send(Err(reason)).await;
+    });
+
+    let body = stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", ARCHIVE_FILE_NAME),
+        ))
+        .streaming(body))
+}
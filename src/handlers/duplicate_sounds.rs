@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use actix_web::{
+    get,
+    web::{self, Data},
+    Error, HttpResponse,
+};
+use log::error;
+use serde::Serialize;
+
+use crate::{
+    actions::{
+        fingerprint::duplicate_clusters,
+        sounds::{fetch_fingerprints, fetch_sounds_with_tags},
+    },
+    app_state::AppState,
+    models::SoundWithTags,
+};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorPayload {
+    message: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DuplicateCluster {
+    sounds: Vec<SoundWithTags>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DuplicateSoundsResponse {
+    clusters: Vec<DuplicateCluster>,
+}
+
+/// Groups the sounds of the library that sound alike. Sounds without
+/// a fingerprint are left out until the `backfill` subcommand runs.
+///
+/// Has to be registered before `GET /sounds/{sound_id}`,
+/// otherwise "duplicates" is taken as a sound id.
+#[get("/sounds/duplicates")]
+pub async fn duplicate_sounds_handler(data: Data<AppState>) -> Result<HttpResponse, Error> {
+    let database_pool = data.database_pool.clone();
+    let result = web::block(move || {
+        let database_connection = database_pool
+            .get()
+            .expect("couldn't get db connection from pool");
+
+        let clusters = duplicate_clusters(fetch_fingerprints(&database_connection)?);
+        let mut sounds = fetch_sounds_with_tags(&database_connection)?
+            .into_iter()
+            .map(|sound| (sound.id.clone(), sound))
+            .collect::<HashMap<String, SoundWithTags>>();
+
+        Ok::<_, diesel::result::Error>(
+            clusters
+                .into_iter()
+                .map(|sound_ids| DuplicateCluster {
+                    sounds: sound_ids
+                        .iter()
+                        .filter_map(|sound_id| sounds.remove(sound_id))
+                        .collect(),
+                })
+                .collect::<Vec<DuplicateCluster>>(),
+        )
+    })
+    .await?;
+
+    match result {
+        Ok(clusters) => Ok(HttpResponse::Ok().json(DuplicateSoundsResponse { clusters })),
+        Err(reason) => {
+            error!("Failed to find duplicate sounds. Reason: {:?}", reason);
+            Ok(HttpResponse::InternalServerError().json(ErrorPayload {
+                message: "Server failed to find duplicate sounds.".to_string(),
+            }))
+        }
+    }
+}
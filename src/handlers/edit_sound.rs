@@ -0,0 +1,396 @@
+use std::path::{Path, PathBuf};
+
+use actix_broker::{Broker, SystemBroker};
+use actix_web::{
+    error::ErrorInternalServerError,
+    post,
+    web::{self, Data, Json},
+    Error, HttpResponse,
+};
+use log::error;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    actions::{
+        audio::{apply_sound_edit, probe_duration, SoundEdit},
+        fs::{hash_file, remove_sound_file},
+        ingest::{
+            fingerprint_sound_file, process_sound_file, remove_derived_files, ProcessedSound,
+        },
+        sounds::{fetch_sound_by_id, set_sound_file},
+    },
+    app_state::AppState,
+    lock::{lock_actor::SoundReservation, messages::LockError},
+    models::{Sound, SoundFileChangeset},
+    websocket::messages::WsSoundsChanged,
+};
+
+#[derive(Deserialize)]
+pub struct EditSoundRequestPath {
+    sound_id: String,
+}
+
+/// Every value is in milliseconds. `startMs` defaults to the
+/// start of the sound and `endMs` to the end of the sound.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditSoundPayload {
+    start_ms: Option<i64>,
+    end_ms: Option<i64>,
+    fade_in_ms: Option<i64>,
+    fade_out_ms: Option<i64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorPayload {
+    message: String,
+}
+
+impl EditSoundPayload {
+    fn into_edit(self, duration_ms: i64) -> Result<SoundEdit, String> {
+        let edit = SoundEdit {
+            start_ms: self.start_ms.unwrap_or(0),
+            end_ms: self.end_ms.unwrap_or(duration_ms).min(duration_ms),
+            fade_in_ms: self.fade_in_ms.unwrap_or(0),
+            fade_out_ms: self.fade_out_ms.unwrap_or(0),
+        };
+
+        if edit.start_ms < 0 || edit.fade_in_ms < 0 || edit.fade_out_ms < 0 {
+            return Err("Offsets and fade durations can't be negative.".to_string());
+        }
+
+        if edit.end_ms <= edit.start_ms {
+            return Err(format!(
+                "`endMs` must be greater than `startMs` and the sound is {}ms long.",
+                duration_ms
+            ));
+        }
+
+        if edit.fade_in_ms + edit.fade_out_ms > edit.end_ms - edit.start_ms {
+            return Err("Fades can't be longer than the edited sound.".to_string());
+        }
+
+        Ok(edit)
+    }
+}
+
+fn audio_path(audio_folder_path: &Path, file_name: &str, extension: &str) -> PathBuf {
+    let mut path = audio_folder_path.join(file_name);
+    path.set_extension(extension);
+    path
+}
+
+async fn fetch_sound(sound_id: String, data: &Data<AppState>) -> Result<Option<Sound>, Error> {
+    let database_pool = data.database_pool.clone();
+
+    Ok(web::block(move || {
+        let database_connection = database_pool
+            .get()
+            .expect("couldn't get db connection from pool");
+
+        fetch_sound_by_id(sound_id, &database_connection)
+    })
+    .await?)
+}
+
+/// Keeps the sound from being played while its file is replaced,
+/// answering with a 409 when it's playing or already being changed.
+async fn reserve_sound(
+    sound_id: &str,
+    action: &str,
+    data: &Data<AppState>,
+) -> Result<Result<SoundReservation, HttpResponse>, Error> {
+    let reservation =
+        SoundReservation::reserve(data.sound_lock_actor_addr.clone(), sound_id.to_string())
+            .await
+            .map_err(ErrorInternalServerError)?;
+
+    Ok(reservation.map_err(|reason| {
+        let state = match reason {
+            LockError::Reserved => "being changed",
+            LockError::Locked | LockError::Playing => "currently playing",
+        };
+
+        HttpResponse::Conflict().json(ErrorPayload {
+            message: format!(
+                "Sound with id {} is {} and can't be {}.",
+                sound_id, state, action
+            ),
+        })
+    }))
+}
+
+/// Points the sound to its new audio file and cleans up
+/// the files of the one it was using before.
+async fn replace_sound_file(
+    sound: Sound,
+    changeset: SoundFileChangeset,
+    data: &Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let audio_folder_path = Path::new(&data.audio_folder_path);
+    let database_pool = data.database_pool.clone();
+    let sound_id = sound.id.clone();
+    let changeset_clone = changeset.clone();
+    let result = web::block(move || {
+        let database_connection = database_pool
+            .get()
+            .expect("couldn't get db connection from pool");
+
+        set_sound_file(sound_id, changeset_clone, &database_connection)
+    })
+    .await?;
+
+    let updated_sound = match result {
+        Ok(updated_sound) => updated_sound,
+        Err(reason) => {
+            error!("Failed to update sound file. Reason: {:?}", reason);
+            let _ = remove_derived_files(
+                &changeset.file_name,
+                changeset.playback_file_name,
+                audio_folder_path,
+            )
+            .await;
+            return Ok(HttpResponse::InternalServerError().json(ErrorPayload {
+                message: "Server failed to update sound in database.".to_string(),
+            }));
+        }
+    };
+
+    if let Err(reason) = remove_derived_files(
+        &sound.file_name,
+        sound.playback_file_name,
+        audio_folder_path,
+    )
+    .await
+    {
+        error!(
+            "Failed to remove derived files for sound with id {}. Reason: {:?}",
+            sound.id, reason
+        );
+    }
+
+    /*
+     * The previous file is only an edit output when the
+     * sound already had an original, which is kept.
+     */
+    if sound.original_file_name.is_some() {
+        if let Err(reason) =
+            remove_sound_file(sound.file_name, sound.extension, audio_folder_path).await
+        {
+            error!(
+                "Failed to remove edited audio file for sound with id {}. Reason: {:?}",
+                sound.id, reason
+            );
+        }
+    }
+
+    Broker::<SystemBroker>::issue_async(WsSoundsChanged {});
+
+    match updated_sound {
+        Some(updated_sound) => Ok(HttpResponse::Ok().json(updated_sound)),
+        None => Ok(HttpResponse::NotFound().json(ErrorPayload {
+            message: format!("Failed to find sound with id: {}", sound.id),
+        })),
+    }
+}
+
+/// Trims and fades a sound into a new audio file.
+///
+/// Edits are always applied to the original upload, so editing
+/// twice doesn't stack trims and `POST /sounds/{sound_id}/revert`
+/// can restore it.
+#[post("/sounds/{sound_id}/edit")]
+pub async fn edit_sound_handler(
+    path: web::Path<EditSoundRequestPath>,
+    json: Json<EditSoundPayload>,
+    data: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let _reservation = match reserve_sound(&path.sound_id, "edited", &data).await? {
+        Ok(reservation) => reservation,
+        Err(response) => return Ok(response),
+    };
+
+    let sound = match fetch_sound(path.sound_id.clone(), &data).await? {
+        Some(sound) => sound,
+        None => {
+            return Ok(HttpResponse::NotFound().json(ErrorPayload {
+                message: format!("Failed to find sound with id: {}", path.sound_id),
+            }));
+        }
+    };
+
+    let (source_file_name, source_extension, source_file_hash) = match (
+        sound.original_file_name.clone(),
+        sound.original_extension.clone(),
+        sound.original_file_hash.clone(),
+    ) {
+        (Some(file_name), Some(extension), Some(file_hash)) => (file_name, extension, file_hash),
+        _ => (
+            sound.file_name.clone(),
+            sound.extension.clone(),
+            sound.file_hash.clone(),
+        ),
+    };
+
+    let audio_folder_path = Path::new(&data.audio_folder_path);
+    let source_path = audio_path(audio_folder_path, &source_file_name, &source_extension);
+
+    let duration_ms = match probe_duration(&source_path).await {
+        Ok(duration) => (duration * 1000.0).round() as i64,
+        Err(reason) => {
+            error!(
+                "Failed to read duration of sound with id {}. Reason: {:?}",
+                sound.id, reason
+            );
+            return Ok(HttpResponse::InternalServerError().json(ErrorPayload {
+                message: "Server failed to read the sound duration.".to_string(),
+            }));
+        }
+    };
+
+    let edit = match json.into_inner().into_edit(duration_ms) {
+        Ok(edit) => edit,
+        Err(message) => return Ok(HttpResponse::BadRequest().json(ErrorPayload { message })),
+    };
+
+    let file_name = Uuid::new_v4().to_string();
+    let edited_path = audio_path(audio_folder_path, &file_name, &source_extension);
+
+    let edit_result = apply_sound_edit(&source_path, &edited_path, edit).await;
+    let file_hash = match edit_result {
+        Ok(()) => {
+            let edited_path = edited_path.clone();
+            web::block(move || hash_file(&edited_path)).await?
+        }
+        Err(reason) => Err(reason),
+    };
+
+    let file_hash = match file_hash {
+        Ok(file_hash) => file_hash,
+        Err(reason) => {
+            error!(
+                "Failed to edit sound with id {}. Reason: {:?}",
+                sound.id, reason
+            );
+            let _ = tokio::fs::remove_file(&edited_path).await;
+            return Ok(HttpResponse::InternalServerError().json(ErrorPayload {
+                message: "Server failed to edit the sound.".to_string(),
+            }));
+        }
+    };
+
+    let ProcessedSound {
+        playback_file_name,
+        loudness_lufs,
+        true_peak_dbtp,
+        metadata,
+    } = process_sound_file(
+        &edited_path,
+        &file_name,
+        &file_hash,
+        audio_folder_path,
+        None,
+        data.loudness_target_lufs,
+    )
+    .await;
+    let fingerprint = fingerprint_sound_file(&edited_path).await;
+
+    let changeset = SoundFileChangeset {
+        extension: source_extension.clone(),
+        file_name,
+        file_hash,
+        playback_file_name,
+        loudness_lufs,
+        duration_ms: metadata.duration_ms,
+        sample_rate: metadata.sample_rate,
+        channels: metadata.channels,
+        codec: metadata.codec,
+        original_file_name: Some(source_file_name),
+        original_extension: Some(source_extension),
+        original_file_hash: Some(source_file_hash),
+        fingerprint,
+        true_peak_dbtp,
+    };
+
+    let response = replace_sound_file(sound, changeset, &data).await?;
+
+    if !response.status().is_success() {
+        let _ = tokio::fs::remove_file(&edited_path).await;
+    }
+
+    Ok(response)
+}
+
+/// Restores the audio file a sound had before it was edited.
+#[post("/sounds/{sound_id}/revert")]
+pub async fn revert_sound_handler(
+    path: web::Path<EditSoundRequestPath>,
+    data: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let _reservation = match reserve_sound(&path.sound_id, "reverted", &data).await? {
+        Ok(reservation) => reservation,
+        Err(response) => return Ok(response),
+    };
+
+    let sound = match fetch_sound(path.sound_id.clone(), &data).await? {
+        Some(sound) => sound,
+        None => {
+            return Ok(HttpResponse::NotFound().json(ErrorPayload {
+                message: format!("Failed to find sound with id: {}", path.sound_id),
+            }));
+        }
+    };
+
+    let (file_name, extension, file_hash) = match (
+        sound.original_file_name.clone(),
+        sound.original_extension.clone(),
+        sound.original_file_hash.clone(),
+    ) {
+        (Some(file_name), Some(extension), Some(file_hash)) => (file_name, extension, file_hash),
+        _ => {
+            return Ok(HttpResponse::Conflict().json(ErrorPayload {
+                message: format!("Sound with id {} has not been edited.", sound.id),
+            }));
+        }
+    };
+
+    let audio_folder_path = Path::new(&data.audio_folder_path);
+    let original_path = audio_path(audio_folder_path, &file_name, &extension);
+
+    let ProcessedSound {
+        playback_file_name,
+        loudness_lufs,
+        true_peak_dbtp,
+        metadata,
+    } = process_sound_file(
+        &original_path,
+        &file_name,
+        &file_hash,
+        audio_folder_path,
+        None,
+        data.loudness_target_lufs,
+    )
+    .await;
+    let fingerprint = fingerprint_sound_file(&original_path).await;
+
+    let changeset = SoundFileChangeset {
+        extension,
+        file_name,
+        file_hash,
+        playback_file_name,
+        loudness_lufs,
+        duration_ms: metadata.duration_ms,
+        sample_rate: metadata.sample_rate,
+        channels: metadata.channels,
+        codec: metadata.codec,
+        original_file_name: None,
+        original_extension: None,
+        original_file_hash: None,
+        fingerprint,
+        true_peak_dbtp,
+    };
+
+    replace_sound_file(sound, changeset, &data).await
+}
@@ -0,0 +1,45 @@
+use actix_web::{
+    get,
+    web::{self, Data},
+    Error, HttpResponse,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{actions::sounds::fetch_sound_with_tags_by_id, app_state::AppState};
+
+#[derive(Deserialize)]
+pub struct GetSoundRequestPath {
+    sound_id: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorPayload {
+    message: String,
+}
+
+/// Has to be registered after `GET /sounds/search`,
+/// otherwise "search" is taken as a sound id.
+#[get("/sounds/{sound_id}")]
+pub async fn get_sound_handler(
+    path: web::Path<GetSoundRequestPath>,
+    data: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let sound_id = path.sound_id.clone();
+    let sound = web::block(move || {
+        let database_connection = &data
+            .database_pool
+            .get()
+            .expect("couldn't get db connection from pool");
+
+        fetch_sound_with_tags_by_id(sound_id, database_connection)
+    })
+    .await?;
+
+    match sound {
+        Some(sound) => Ok(HttpResponse::Ok().json(sound)),
+        None => Ok(HttpResponse::NotFound().json(ErrorPayload {
+            message: format!("Failed to find sound with id: {}", path.sound_id),
+        })),
+    }
+}
@@ -0,0 +1,209 @@
+use std::path::{Path, PathBuf};
+
+use actix_broker::{Broker, SystemBroker};
+use actix_multipart::Multipart;
+use actix_web::{
+    post,
+    web::{self, Data},
+    Error, HttpResponse,
+};
+use log::error;
+use serde::Serialize;
+use serenity::futures::TryStreamExt;
+use tokio::{fs::File, io::AsyncWriteExt};
+
+use crate::{
+    actions::{
+        archive::{open_sounds_archive, read_sounds_archive_entry, ArchiveEntry},
+        fs::TemporaryPath,
+    },
+    app_state::AppState,
+    handlers::upload::{
+        upload_payload_file, UploadFailure, UploadFailureCode, UploadOptions, UploadSuccess,
+    },
+    websocket::messages::WsSoundsChanged,
+};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorPayload {
+    message: String,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ImportResponse {
+    successful: Vec<UploadSuccess>,
+    failed: Vec<UploadFailure>,
+}
+
+/// Imports a ZIP archive in the format produced by `GET /download-sounds`.
+///
+/// Every audio file goes through the same validation and deduplication
+/// as `POST /upload`, with names and tags restored from the manifest.
+#[post("/import")]
+pub async fn import_handler(
+    mut payload: Multipart,
+    data: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let audio_folder_path = Path::new(&data.audio_folder_path);
+    let max_archive_bytes = data.import_limits.max_archive_bytes;
+    // Removes the archive on every way out of the handler
+    let archive_path = TemporaryPath::new(audio_folder_path);
+    let mut has_archive = false;
+
+    /*
+     * The archive is written to disk as it arrives,
+     * since it can be as big as the entire library.
+     */
+    while let Some(mut field) = payload.try_next().await? {
+        if field.content_disposition().get_filename().is_none() {
+            continue;
+        }
+
+        let mut archive_file = File::create(archive_path.path()).await?;
+        let mut archive_size = 0;
+
+        while let Some(chunk) = field.try_next().await? {
+            archive_size += chunk.len() as u64;
+
+            if archive_size > max_archive_bytes {
+                return Ok(HttpResponse::PayloadTooLarge().json(ErrorPayload {
+                    message: format!(
+                        "Archive is bigger than the limit of {} bytes.",
+                        max_archive_bytes
+                    ),
+                }));
+            }
+
+            archive_file.write_all(&chunk).await?;
+        }
+
+        archive_file.flush().await?;
+        has_archive = true;
+        break;
+    }
+
+    if !has_archive {
+        return Ok(HttpResponse::BadRequest().json(ErrorPayload {
+            message: "Request must contain a ZIP archive file.".to_string(),
+        }));
+    }
+
+    let result = import_archive(archive_path.path().to_path_buf(), &data).await;
+
+    let response = match result {
+        Ok(response) => response,
+        Err(reason) => {
+            error!("Failed to import archive. Reason: {:?}", reason);
+            return Ok(HttpResponse::BadRequest().json(ErrorPayload {
+                message: format!("Failed to read archive: {}", reason),
+            }));
+        }
+    };
+
+    if !response.successful.is_empty() {
+        Broker::<SystemBroker>::issue_async(WsSoundsChanged {});
+    }
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn import_archive(
+    archive_path: PathBuf,
+    data: &Data<AppState>,
+) -> Result<ImportResponse, std::io::Error> {
+    let audio_folder_path = Path::new(&data.audio_folder_path);
+    let mut successful_uploads: Vec<UploadSuccess> = vec![];
+    let mut failed_uploads: Vec<UploadFailure> = vec![];
+
+    let max_files = data.import_limits.max_files;
+    let max_file_bytes = data.upload_limits.max_file_bytes;
+    let (mut archive, manifest) = web::block(move || open_sounds_archive(&archive_path, max_files))
+        .await
+        .map_err(|reason| std::io::Error::other(reason.to_string()))??;
+
+    for index in 0..archive.len() {
+        let entry_audio_folder_path = audio_folder_path.to_path_buf();
+        let (returned_archive, entry) = web::block(move || {
+            let entry = read_sounds_archive_entry(
+                &mut archive,
+                index,
+                &entry_audio_folder_path,
+                max_file_bytes,
+            );
+            (archive, entry)
+        })
+        .await
+        .map_err(|reason| std::io::Error::other(reason.to_string()))?;
+
+        archive = returned_archive;
+
+        let entry = match entry {
+            Ok(Some(entry)) => entry,
+            Ok(None) => continue,
+            Err(reason) => {
+                failed_uploads.push(UploadFailure::new(
+                    &format!("entry #{}", index),
+                    UploadFailureCode::InternalError,
+                    reason,
+                ));
+                continue;
+            }
+        };
+
+        let ArchiveEntry { file_name, sound } = entry;
+        let sound = match sound {
+            Ok(sound) => sound,
+            Err(reason) => {
+                failed_uploads.push(UploadFailure::staging(&file_name, reason));
+                continue;
+            }
+        };
+
+        let manifest_entry = manifest.as_ref().and_then(|manifest| {
+            manifest
+                .sounds
+                .iter()
+                .find(|manifest_entry| manifest_entry.file_name == file_name)
+        });
+
+        let (name, slugs) = match manifest_entry {
+            Some(manifest_entry) => (manifest_entry.name.clone(), manifest_entry.tags.clone()),
+            None => (
+                Path::new(&file_name)
+                    .file_stem()
+                    .and_then(|file_stem| file_stem.to_str())
+                    .unwrap_or(&file_name)
+                    .to_string(),
+                vec![],
+            ),
+        };
+
+        let upload_result = upload_payload_file(
+            sound,
+            &file_name,
+            &name,
+            audio_folder_path,
+            data.database_pool.clone(),
+            UploadOptions {
+                slugs,
+                max_duration_seconds: data.upload_limits.max_duration_seconds,
+                trim_silence_below_db: None,
+                duplicate_policy: data.duplicate_policy,
+                loudness_target_lufs: data.loudness_target_lufs,
+            },
+        )
+        .await;
+
+        match upload_result {
+            Ok(successful) => successful_uploads.push(successful),
+            Err(failure) => failed_uploads.push(failure),
+        }
+    }
+
+    Ok(ImportResponse {
+        successful: successful_uploads,
+        failed: failed_uploads,
+    })
+}
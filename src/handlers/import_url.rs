@@ -0,0 +1,358 @@
+use std::{net::IpAddr, path::Path};
+
+use actix_broker::{Broker, SystemBroker};
+use actix_web::{
+    post,
+    web::{Data, Json},
+    Error, HttpResponse,
+};
+use reqwest::{header::CONTENT_TYPE, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::{
+        fs::{StageError, StagedSound, StagedSoundWriter},
+        remote::{get_remote_file, is_public_address},
+        slugs::normalize_slugs,
+    },
+    app_state::AppState,
+    handlers::upload::{
+        upload_payload_file, UploadFailure, UploadFailureCode, UploadOptions, UploadSuccess,
+    },
+    websocket::messages::WsSoundsChanged,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportUrlPayload {
+    url: String,
+    name: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    trim_silence: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorPayload {
+    message: String,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ImportUrlResponse {
+    successful: Vec<UploadSuccess>,
+    failed: Vec<UploadFailure>,
+}
+
+/// Downloads a sound from a direct audio URL and stores it
+/// through the same checks and limits as `POST /upload`.
+#[post("/import-url")]
+pub async fn import_url_handler(
+    json: Json<ImportUrlPayload>,
+    data: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let payload = json.into_inner();
+
+    let url = match Url::parse(&payload.url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => url,
+        _ => {
+            return Ok(HttpResponse::BadRequest().json(ErrorPayload {
+                message: "`url` must be a valid http or https URL.".to_string(),
+            }));
+        }
+    };
+
+    let filename = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|segment| !segment.trim().is_empty())
+        .unwrap_or("sound")
+        .to_string();
+
+    let name = payload
+        .name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .or_else(|| {
+            Path::new(&filename)
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .map(String::from)
+        })
+        .unwrap_or_else(|| filename.clone());
+
+    let audio_folder_path = Path::new(&data.audio_folder_path);
+    let fetch_result = fetch_sound(
+        url,
+        &filename,
+        audio_folder_path,
+        data.upload_limits.max_file_bytes,
+        is_public_address,
+    )
+    .await;
+
+    let result = match fetch_result {
+        Ok(sound) => {
+            upload_payload_file(
+                sound,
+                &filename,
+                &name,
+                audio_folder_path,
+                data.database_pool.clone(),
+                UploadOptions {
+                    slugs: normalize_slugs(payload.tags),
+                    max_duration_seconds: data.upload_limits.max_duration_seconds,
+                    trim_silence_below_db: if payload.trim_silence {
+                        Some(data.silence_threshold_db)
+                    } else {
+                        None
+                    },
+                    duplicate_policy: data.duplicate_policy,
+                    loudness_target_lufs: data.loudness_target_lufs,
+                },
+            )
+            .await
+        }
+        Err(failure) => Err(failure),
+    };
+
+    let response = match result {
+        Ok(successful) => {
+            Broker::<SystemBroker>::issue_async(WsSoundsChanged {});
+
+            ImportUrlResponse {
+                successful: vec![successful],
+                failed: vec![],
+            }
+        }
+        Err(failure) => ImportUrlResponse {
+            successful: vec![],
+            failed: vec![failure],
+        },
+    };
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Streams the response body to a staged file, enforcing the upload size limit.
+/// Only URLs resolving to addresses accepted by `is_allowed` are fetched.
+async fn fetch_sound(
+    url: Url,
+    filename: &str,
+    audio_folder_path: &Path,
+    max_file_bytes: u64,
+    is_allowed: fn(IpAddr) -> bool,
+) -> Result<StagedSound, UploadFailure> {
+    let download_error = |reason: &dyn ToString| {
+        UploadFailure::new(
+            filename,
+            UploadFailureCode::DownloadFailed,
+            reason.to_string(),
+        )
+    };
+    let internal_error = |reason: &dyn ToString| {
+        UploadFailure::new(
+            filename,
+            UploadFailureCode::InternalError,
+            reason.to_string(),
+        )
+    };
+
+    let mut response = get_remote_file(url, is_allowed)
+        .await
+        .map_err(|reason| download_error(&reason))?;
+
+    /*
+     * The content type is only a hint, the file type is
+     * still identified from its content once downloaded.
+     */
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .unwrap_or_default();
+
+    if !(content_type.is_empty()
+        || content_type.starts_with("audio/")
+        || content_type.starts_with("video/webm")
+        || content_type.starts_with("application/octet-stream"))
+    {
+        return Err(UploadFailure::new(
+            filename,
+            UploadFailureCode::InvalidFileType,
+            format!("URL doesn't point to an audio file: {}", content_type),
+        ));
+    }
+
+    // Refused upfront when the server announces it, the writer still enforces it
+    if response
+        .content_length()
+        .is_some_and(|length| length > max_file_bytes)
+    {
+        return Err(UploadFailure::staging(
+            filename,
+            StageError::TooLarge(max_file_bytes),
+        ));
+    }
+
+    let mut writer = StagedSoundWriter::create(audio_folder_path, max_file_bytes)
+        .await
+        .map_err(|reason| internal_error(&reason))?;
+
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|reason| download_error(&reason))?
+    {
+        writer
+            .write_chunk(&chunk)
+            .await
+            .map_err(|reason| UploadFailure::staging(filename, reason))?;
+    }
+
+    writer
+        .finish()
+        .await
+        .map_err(|reason| internal_error(&reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_utils::TestFolder;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    const MAX_FILE_BYTES: u64 = 1024;
+
+    /// Answers every connection with the same raw HTTP response.
+    async fn serve(response: Vec<u8>) -> Url {
+        serve_on("127.0.0.1", response).await
+    }
+
+    async fn serve_on(host: &str, response: Vec<u8>) -> Url {
+        let listener = TcpListener::bind((host, 0)).await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![0u8; 4096];
+                let _ = stream.read(&mut request).await;
+                let _ = stream.write_all(&response).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+
+        Url::parse(&format!("http://{}/sounds/clip.wav", address)).unwrap()
+    }
+
+    fn http_response(content_type: &str, content_length: usize, body: &[u8]) -> Vec<u8> {
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            content_type, content_length
+        )
+        .into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    async fn fetch(url: Url, folder: &TestFolder) -> Result<StagedSound, UploadFailure> {
+        fetch_sound(url, "clip.wav", &folder.0, MAX_FILE_BYTES, |_| true).await
+    }
+
+    #[tokio::test]
+    async fn audio_url_is_staged() {
+        let folder = TestFolder::new();
+        let body = b"RIFF\x24\x00\x00\x00WAVEfmt ";
+        let url = serve(http_response("audio/wav", body.len(), body)).await;
+
+        let sound = fetch(url, &folder).await.ok().unwrap();
+
+        assert_eq!(fs::read(sound.path()).unwrap(), body.to_vec());
+        assert_eq!(sound.header, body.to_vec());
+    }
+
+    #[tokio::test]
+    async fn oversized_content_length_is_refused() {
+        let folder = TestFolder::new();
+        let url = serve(http_response("audio/mpeg", 10 * 1024 * 1024, b"")).await;
+
+        let failure = fetch(url, &folder).await.err().unwrap();
+
+        assert_eq!(failure.code, UploadFailureCode::FileTooLarge);
+    }
+
+    #[tokio::test]
+    async fn oversized_body_is_refused() {
+        let folder = TestFolder::new();
+        let body = vec![0u8; MAX_FILE_BYTES as usize + 1];
+        let mut response =
+            b"HTTP/1.1 200 OK\r\nContent-Type: audio/mpeg\r\nConnection: close\r\n\r\n".to_vec();
+        response.extend_from_slice(&body);
+        let url = serve(response).await;
+
+        let failure = fetch(url, &folder).await.err().unwrap();
+
+        assert_eq!(failure.code, UploadFailureCode::FileTooLarge);
+        assert_eq!(fs::read_dir(&folder.0).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn non_audio_body_is_refused() {
+        let folder = TestFolder::new();
+        let body = b"<html></html>";
+        let url = serve(http_response("text/html", body.len(), body)).await;
+
+        let failure = fetch(url, &folder).await.err().unwrap();
+
+        assert_eq!(failure.code, UploadFailureCode::InvalidFileType);
+    }
+
+    #[tokio::test]
+    async fn private_addresses_are_refused() {
+        let folder = TestFolder::new();
+        let body = b"RIFF";
+        let url = serve(http_response("audio/wav", body.len(), body)).await;
+
+        let failure = fetch_sound(
+            url,
+            "clip.wav",
+            &folder.0,
+            MAX_FILE_BYTES,
+            is_public_address,
+        )
+        .await
+        .err()
+        .unwrap();
+
+        assert_eq!(failure.code, UploadFailureCode::DownloadFailed);
+    }
+
+    #[tokio::test]
+    async fn redirects_are_checked() {
+        let folder = TestFolder::new();
+        let target = serve_on("127.0.0.2", http_response("audio/wav", 4, b"RIFF")).await;
+        let redirect = format!(
+            "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            target
+        );
+        let url = serve(redirect.into_bytes()).await;
+
+        let sound = fetch(url.clone(), &folder).await.ok().unwrap();
+        assert_eq!(sound.header, b"RIFF".to_vec());
+
+        let failure = fetch_sound(url, "clip.wav", &folder.0, MAX_FILE_BYTES, |address| {
+            address == IpAddr::from([127, 0, 0, 1])
+        })
+        .await
+        .err()
+        .unwrap();
+        assert_eq!(failure.code, UploadFailureCode::DownloadFailed);
+    }
+}
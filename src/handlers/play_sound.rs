@@ -1,15 +1,29 @@
 use std::path::Path;
 
 use actix_web::{
+    error::ErrorInternalServerError,
     post,
     web::{self, Data, Json},
     Error, HttpResponse,
 };
-use log::debug;
+use log::{debug, error};
 use serde::{Deserialize, Serialize};
 use teloxide::{prelude::*, types::InputFile};
 
-use crate::{actions::sounds::fetch_sound_by_id, app_state::AppState, discord::actor::PlayAudio};
+use crate::{
+    actions::{
+        audio::{
+            loudness_gain, telegram_rendition_path, transcode_mp3, PLAYBACK_EXTENSION,
+            TELEGRAM_EXTENSION,
+        },
+        fs::TemporaryPath,
+        sounds::{fetch_sound_by_id, increment_play_count},
+    },
+    app_state::AppState,
+    discord::actor::PlayAudio,
+    handlers::voice::{guild_id, parse_discord_id},
+    lock::{lock_actor::SoundReservation, messages::LockError},
+};
 
 #[derive(Deserialize, Serialize, Clone, Copy, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -23,6 +37,10 @@ enum Client {
 pub struct PlaySoundPayload {
     sound_id: String,
     client: Client,
+    /// Discord guild to play in, `DISCORD_GUILD_ID` when missing
+    guild_id: Option<String>,
+    /// Discord voice channel to join before playing
+    channel_id: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -38,12 +56,61 @@ struct PlaySoundResponse {
     client: Client,
 }
 
+/// Picks the guild and voice channel a sound is played in.
+fn voice_target(
+    payload: &PlaySoundPayload,
+    data: &Data<AppState>,
+) -> Result<(u64, Option<u64>), Error> {
+    let guild_id = guild_id(&payload.guild_id, data)?;
+    let channel_id = match &payload.channel_id {
+        Some(channel_id) => Some(parse_discord_id(channel_id, "channelId")?),
+        None => None,
+    };
+
+    Ok((guild_id, channel_id))
+}
+
 #[post("/play-sound")]
 pub async fn play_sound_handler(
     data: Data<AppState>,
     json: Json<PlaySoundPayload>,
 ) -> Result<HttpResponse, Error> {
     let audio_folder_path = Path::new(&data.audio_folder_path);
+
+    /*
+     * Telegram plays keep the sound reserved until it's sent, so it
+     * can't be deleted or edited while its rendition is being made.
+     * Discord plays are held by the sound lock the actor takes instead.
+     */
+    let _reservation = match json.client {
+        Client::Telegram => {
+            let reservation = SoundReservation::reserve(
+                data.sound_lock_actor_addr.clone(),
+                json.sound_id.clone(),
+            )
+            .await
+            .map_err(ErrorInternalServerError)?;
+
+            match reservation {
+                Ok(reservation) => Some(reservation),
+                Err(reason) => {
+                    let state = match reason {
+                        LockError::Reserved => "being changed",
+                        LockError::Locked | LockError::Playing => "currently playing",
+                    };
+
+                    return Ok(HttpResponse::Conflict().json(ErrorPayload {
+                        message: format!(
+                            "Sound with id {} is {} and can't be sent.",
+                            json.sound_id, state
+                        ),
+                    }));
+                }
+            }
+        }
+        Client::Discord => None,
+    };
+
     let data_clone = data.clone();
     let sound_id = json.sound_id.clone();
     let sound = web::block(move || {
@@ -78,27 +145,112 @@ pub async fn play_sound_handler(
         }));
     }
 
+    let playback_path = sound
+        .playback_file_name
+        .as_ref()
+        .map(|playback_file_name| {
+            let mut path = audio_folder_path.join(playback_file_name);
+            path.set_extension(PLAYBACK_EXTENSION);
+            path
+        })
+        .filter(|path| path.exists());
+
+    let volume = sound.loudness_lufs.map_or(1.0, |loudness_lufs| {
+        loudness_gain(
+            loudness_lufs,
+            sound.true_peak_dbtp,
+            data.loudness_target_lufs,
+        )
+    });
+
     debug!("json client is {:?}", &json.client);
 
     match json.client {
         Client::Discord => {
+            let (guild_id, channel_id) = voice_target(&json, &data)?;
+
+            /*
+             * Sounds that didn't play aren't counted,
+             * and the caller is told why.
+             */
             data.discord_actor_addr
-                .send(PlayAudio { audio_path, sound })
+                .send(PlayAudio {
+                    guild_id,
+                    channel_id,
+                    audio_path,
+                    playback_path,
+                    volume,
+                    sound,
+                })
                 .await
-                .expect("Failed to play audio");
+                .map_err(ErrorInternalServerError)??;
         }
         Client::Telegram => {
             let chat_id = data.telegram_chat_id.clone();
-            let file = InputFile::file(&audio_path).file_name(sound.name.clone());
+
+            let telegram_path =
+                telegram_rendition_path(audio_folder_path, &sound.file_name, &sound.extension);
+
+            /*
+             * Renditions are made at ingest, the ones missing after
+             * a failed transcode are made once and kept for next plays.
+             */
+            if let Some(telegram_path) = telegram_path.as_ref().filter(|path| !path.exists()) {
+                let temporary_path =
+                    TemporaryPath::with_extension(audio_folder_path, TELEGRAM_EXTENSION);
+                let result = match transcode_mp3(&audio_path, temporary_path.path()).await {
+                    Ok(()) => tokio::fs::rename(temporary_path.path(), telegram_path).await,
+                    Err(reason) => Err(reason),
+                };
+
+                if let Err(reason) = result {
+                    error!(
+                        "Failed to transcode sound with id {} for telegram. Reason: {:?}",
+                        sound.id, reason
+                    );
+                    return Ok(HttpResponse::InternalServerError().json(ErrorPayload {
+                        message: format!(
+                            "Failed to prepare sound with id {} for telegram",
+                            json.sound_id
+                        ),
+                    }));
+                }
+            }
+
+            let file = InputFile::file(telegram_path.unwrap_or_else(|| audio_path.clone()))
+                .file_name(sound.name.clone());
             debug!(
                 "sending audio at {:?} to telegram chat id: {:?}",
                 sound.name, chat_id
             );
 
-            let _ = data.telegram_bot.send_audio(chat_id, file).await;
+            if let Err(reason) = data.telegram_bot.send_audio(chat_id, file).await {
+                error!(
+                    "Failed to send sound with id {} to telegram. Reason: {:?}",
+                    sound.id, reason
+                );
+                return Ok(HttpResponse::InternalServerError().json(ErrorPayload {
+                    message: format!("Failed to send sound with id {} to telegram", json.sound_id),
+                }));
+            }
         }
     }
 
+    let database_pool = data.database_pool.clone();
+    let sound_id = json.sound_id.clone();
+    let play_count_result = web::block(move || {
+        let database_connection = database_pool
+            .get()
+            .expect("couldn't get db connection from pool");
+
+        increment_play_count(sound_id, &database_connection)
+    })
+    .await?;
+
+    if let Err(reason) = play_count_result {
+        error!("Failed to increment play count. Reason: {:?}", reason);
+    }
+
     Ok(HttpResponse::Ok().json(PlaySoundResponse {
         sound_id: json.sound_id.clone(),
         client: json.client,
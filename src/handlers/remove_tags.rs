@@ -0,0 +1,92 @@
+use actix_broker::{Broker, SystemBroker};
+use actix_web::{
+    delete,
+    web::{Data, Json, Path},
+    Error, HttpResponse,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::tags::{remove_tags, RemoveTagsResult},
+    app_state::AppState,
+    websocket::messages::WsSoundsChanged,
+};
+
+#[derive(Deserialize)]
+pub struct RemoveTagRequestPath {
+    sound_id: String,
+    slug: String,
+}
+
+#[derive(Deserialize)]
+pub struct RemoveTagsRequestPath {
+    sound_id: String,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveTagsRequestBody {
+    tags: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorPayload {
+    message: String,
+}
+
+#[delete("/sounds/{sound_id}/tags/{slug}")]
+pub async fn remove_tag_handler(
+    path: Path<RemoveTagRequestPath>,
+    data: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let result = remove_tags(
+        path.sound_id.clone(),
+        vec![path.slug.clone()],
+        data.database_pool.clone(),
+    )
+    .await?;
+
+    Ok(into_response(&path.sound_id, result))
+}
+
+#[delete("/sounds/{sound_id}/tags")]
+pub async fn remove_tags_handler(
+    path: Path<RemoveTagsRequestPath>,
+    body: Json<RemoveTagsRequestBody>,
+    data: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    if body.tags.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(ErrorPayload {
+            message: "At least one tag must be given.".to_string(),
+        }));
+    }
+
+    let result = remove_tags(
+        path.sound_id.clone(),
+        body.tags.clone(),
+        data.database_pool.clone(),
+    )
+    .await?;
+
+    Ok(into_response(&path.sound_id, result))
+}
+
+fn into_response(sound_id: &str, result: RemoveTagsResult) -> HttpResponse {
+    match result {
+        RemoveTagsResult::Removed(updated_sound) => {
+            Broker::<SystemBroker>::issue_async(WsSoundsChanged {});
+            HttpResponse::Ok().json(updated_sound)
+        }
+        RemoveTagsResult::SoundNotFound => HttpResponse::NotFound().json(ErrorPayload {
+            message: format!("Failed to find sound with id: {}", sound_id),
+        }),
+        RemoveTagsResult::TagsNotFound(slugs) => HttpResponse::NotFound().json(ErrorPayload {
+            message: format!(
+                "Sound with id {} doesn't have the tags: {}",
+                sound_id,
+                slugs.join(", ")
+            ),
+        }),
+    }
+}
@@ -0,0 +1,58 @@
+use actix_web::{
+    get,
+    web::{self, Data, Query},
+    Error, HttpResponse,
+};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::search::{search_sounds, DEFAULT_SEARCH_LIMIT, MAX_SEARCH_LIMIT},
+    app_state::AppState,
+};
+
+#[derive(Deserialize)]
+pub struct SearchSoundsQuery {
+    q: String,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorPayload {
+    message: String,
+}
+
+#[get("/sounds/search")]
+pub async fn search_sounds_handler(
+    query: Query<SearchSoundsQuery>,
+    data: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let query = query.into_inner();
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .clamp(1, MAX_SEARCH_LIMIT);
+
+    let result = web::block(move || {
+        let database_connection = &data
+            .database_pool
+            .get()
+            .expect("couldn't get db connection from pool");
+
+        search_sounds(&query.q, limit, database_connection)
+    })
+    .await?;
+
+    let response = match result {
+        Ok(sounds) => sounds,
+        Err(reason) => {
+            error!("Failed to search sounds in database. Reason: {:?}", reason);
+            return Ok(HttpResponse::InternalServerError().json(ErrorPayload {
+                message: "Server failed to search sounds in database.".to_string(),
+            }));
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(response))
+}
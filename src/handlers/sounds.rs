@@ -1,11 +1,15 @@
 use actix_web::{
     get,
-    web::{self, Data},
+    web::{self, Data, Query},
     Error, HttpResponse,
 };
+use log::error;
 use serde::Serialize;
 
-use crate::{actions::sounds::fetch_sounds_with_tags, app_state::AppState};
+use crate::{
+    actions::pagination::{fetch_sounds_page, SoundsFilter},
+    app_state::AppState,
+};
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -13,22 +17,38 @@ struct ErrorPayload {
     message: String,
 }
 
+/// Always answers with a `SoundsPage`, holding the first
+/// `DEFAULT_PAGE_SIZE` sounds when no `limit` is given.
 #[get("/sounds")]
-pub async fn sounds_handler(data: Data<AppState>) -> Result<HttpResponse, Error> {
+pub async fn sounds_handler(
+    query: Query<SoundsFilter>,
+    data: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let filter = query.into_inner();
+
+    let cursor = match filter.cursor() {
+        Ok(cursor) => cursor,
+        Err(_) => {
+            return Ok(HttpResponse::BadRequest().json(ErrorPayload {
+                message: "Cursor is not valid for the given sort and order.".to_string(),
+            }));
+        }
+    };
+
     let result = web::block(move || {
         let database_connection = &data
             .database_pool
             .get()
             .expect("couldn't get db connection from pool");
 
-        fetch_sounds_with_tags(database_connection)
+        fetch_sounds_page(&filter, cursor, database_connection)
     })
     .await?;
 
     let response = match result {
-        Ok(sounds) => sounds,
+        Ok(page) => page,
         Err(reason) => {
-            eprintln!("Failed to fetch sounds from database. Reason: {:?}", reason);
+            error!("Failed to fetch sounds from database. Reason: {:?}", reason);
             return Ok(HttpResponse::InternalServerError().json(ErrorPayload {
                 message: "Server failed to fetch sounds from database.".to_string(),
             }));
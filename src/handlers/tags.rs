@@ -0,0 +1,52 @@
+use actix_web::{
+    get,
+    web::{self, Data, Query},
+    Error, HttpResponse,
+};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::tags::{fetch_tags_with_count, TagsSort},
+    app_state::AppState,
+};
+
+#[derive(Deserialize)]
+pub struct TagsQuery {
+    sort: Option<TagsSort>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorPayload {
+    message: String,
+}
+
+#[get("/tags")]
+pub async fn tags_handler(
+    query: Query<TagsQuery>,
+    data: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let sort = query.sort.unwrap_or(TagsSort::Count);
+    let result = web::block(move || {
+        let database_connection = &data
+            .database_pool
+            .get()
+            .expect("couldn't get db connection from pool");
+
+        fetch_tags_with_count(sort, database_connection)
+    })
+    .await?;
+
+    let response = match result {
+        Ok(tags) => tags,
+        Err(reason) => {
+            error!("Failed to fetch tags from database. Reason: {:?}", reason);
+            return Ok(HttpResponse::InternalServerError().json(ErrorPayload {
+                message: "Server failed to fetch tags from database.".to_string(),
+            }));
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(response))
+}
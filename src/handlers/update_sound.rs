@@ -0,0 +1,76 @@
+use actix_broker::{Broker, SystemBroker};
+use actix_web::{
+    patch,
+    web::{self, Data, Json},
+    Error, HttpResponse,
+};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::sounds::update_sound, app_state::AppState, websocket::messages::WsSoundsChanged,
+};
+
+#[derive(Deserialize)]
+pub struct UpdateSoundRequestPath {
+    sound_id: String,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateSoundRequestBody {
+    name: Option<String>,
+    tags: Option<Vec<String>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorPayload {
+    message: String,
+}
+
+#[patch("/sounds/{sound_id}")]
+pub async fn update_sound_handler(
+    path: web::Path<UpdateSoundRequestPath>,
+    body: Json<UpdateSoundRequestBody>,
+    data: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let sound_id = path.sound_id.clone();
+    let name = body.name.as_ref().map(|name| name.trim().to_string());
+
+    if let Some(name) = &name {
+        if name.is_empty() {
+            return Ok(HttpResponse::BadRequest().json(ErrorPayload {
+                message: "Sound name can't be empty.".to_string(),
+            }));
+        }
+    }
+
+    let database_pool = data.database_pool.clone();
+    let sound_id_clone = sound_id.clone();
+    let slugs = body.tags.clone();
+    let result = web::block(move || {
+        let database_connection = database_pool
+            .get()
+            .expect("couldn't get db connection from pool");
+
+        update_sound(sound_id_clone, name, slugs, &database_connection)
+    })
+    .await?;
+
+    match result {
+        Ok(Some(updated_sound)) => {
+            Broker::<SystemBroker>::issue_async(WsSoundsChanged {});
+            Ok(HttpResponse::Ok().json(updated_sound))
+        }
+        Ok(None) => Ok(HttpResponse::NotFound().json(ErrorPayload {
+            message: format!("Failed to find sound with id: {}", sound_id),
+        })),
+        Err(reason) => {
+            error!("Failed to update sound in database. Reason: {:?}", reason);
+            Ok(HttpResponse::InternalServerError().json(ErrorPayload {
+                message: "Server failed to update sound in database.".to_string(),
+            }))
+        }
+    }
+}
@@ -1,136 +1,122 @@
-use actix_multipart::{Field, Multipart};
+use actix_broker::{Broker, SystemBroker};
+use log::error;
 use serde::Serialize;
-use serenity::futures::{StreamExt, TryStreamExt};
 use uuid::Uuid;
 
-use std::{future::Future, io::ErrorKind, path::Path, pin::Pin};
+use std::{
+    io::ErrorKind,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use actix_web::{
-    dev::Payload,
     post,
-    web::{self, Bytes, Data},
-    Error, FromRequest, HttpRequest, HttpResponse,
+    web::{self, Data},
+    Error, HttpResponse,
 };
 
 use crate::{
     actions::{
-        fs::{save_sound_as_file, validate_sound},
-        sounds::{fetch_sound_by_hash, insert_sound},
+        audio::{apply_sound_edit, detect_silence, verify_decodes, SilenceTrim, SoundEdit},
+        fingerprint::{
+            comparable_lengths, compute_fingerprint, encode_fingerprint, find_near_duplicate,
+        },
+        fs::{remove_sound_file, validate_sound, StageError, StagedSound, TemporaryPath},
+        ingest::{process_sound_file, remove_derived_files},
+        slugs::normalize_slugs,
+        sounds::{fetch_fingerprints_by_length, fetch_sound_by_hash, insert_sound},
     },
-    app_state::{AppState, DatabasePool},
+    app_state::{AppState, DatabasePool, DuplicatePolicy},
     models::Sound,
+    websocket::messages::WsSoundsChanged,
 };
 
+use self::payload::BatchSoundUpload;
+
+mod payload;
+
 #[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
-struct UploadSuccess {
-    id: String,
-    filename: String,
+pub struct UploadSuccess {
+    pub id: String,
+    pub filename: String,
+    /// Silence removed from the uploaded file before it was stored
+    pub trimmed: Option<SilenceTrim>,
+    /// Sound the upload sounds like, when the duplicate policy only warns
+    pub duplicate_of: Option<String>,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
 #[serde(rename_all = "camelCase")]
-struct UploadFailure {
-    filename: String,
-    reason: String,
+pub enum UploadFailureCode {
+    InvalidFileType,
+    Undecodable,
+    InvalidName,
+    DuplicateFilename,
+    AlreadyExists,
+    NearDuplicate,
+    FileTooLarge,
+    TooManyFiles,
+    DurationTooLong,
+    DownloadFailed,
+    InternalError,
 }
 
 #[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
-struct UploadResponse {
-    successful: Vec<UploadSuccess>,
-    failed: Vec<UploadFailure>,
-    tags: Vec<String>,
-}
-
-// https://gist.github.com/Tarkin25/b6274a8a33baa6a72d7763e298f1fb8f
-struct SoundUpload {
-    filename: String,
-    file_content: Vec<Bytes>,
+pub struct UploadFailure {
+    pub filename: String,
+    pub code: UploadFailureCode,
+    pub reason: String,
+    /// Existing sound the file duplicates, for `alreadyExists` and `nearDuplicate`
+    pub duplicate_of: Option<String>,
 }
 
-pub struct BatchSoundUpload {
-    sounds: Vec<SoundUpload>,
-    tags: Vec<String>,
-}
-
-impl BatchSoundUpload {
-    async fn read_string(field: &mut Field) -> Option<String> {
-        let bytes = field.try_next().await;
-
-        if let Ok(Some(bytes)) = bytes {
-            String::from_utf8(bytes.to_vec()).ok()
-        } else {
-            None
+impl UploadFailure {
+    pub fn new(filename: &str, code: UploadFailureCode, reason: impl ToString) -> Self {
+        Self {
+            filename: filename.to_string(),
+            code,
+            reason: reason.to_string(),
+            duplicate_of: None,
         }
     }
 
-    async fn from_multipart(
-        mut multipart: Multipart,
-    ) -> Result<Self, <Self as FromRequest>::Error> {
-        let mut tags: Vec<String> = Vec::new();
-        let mut sounds: Vec<SoundUpload> = Vec::new();
-
-        while let Some(mut field) = multipart.try_next().await? {
-            // A multipart/form-data stream has to contain `content_disposition`
-            // this is where we'll be able to fetch the file name and
-            // the key name for other parts of the payload
-            let content_disposition = field.content_disposition().clone();
-
-            let field_key = content_disposition
-                .get_name()
-                .expect("Content disposition to have a name");
-
-            if field_key == "tags" {
-                let field_content = Self::read_string(&mut field).await;
-
-                if let Some(json_content) = field_content {
-                    tags = serde_json::from_str(&json_content)?;
-                }
-
-                break;
-            }
-
-            let filename = content_disposition
-                .get_filename()
-                .expect("Uploaded file to always contain a filename.");
-
-            let sound_upload = SoundUpload {
-                filename: filename.to_string(),
-                file_content: field
-                    .map(|chunk| chunk.unwrap())
-                    .collect::<Vec<Bytes>>()
-                    .await,
-            };
-
-            sounds.push(sound_upload);
+    /// Failure of a file that couldn't be staged, because of
+    /// the size limit or of the disk.
+    pub fn staging(filename: &str, reason: StageError) -> Self {
+        match reason {
+            StageError::TooLarge(_) => Self::new(filename, UploadFailureCode::FileTooLarge, reason),
+            StageError::Io(reason) => Self::new(filename, UploadFailureCode::InternalError, reason),
         }
+    }
 
-        Ok(BatchSoundUpload { tags, sounds })
+    fn duplicate(filename: &str, code: UploadFailureCode, reason: &str, sound_id: String) -> Self {
+        Self {
+            duplicate_of: Some(sound_id),
+            ..Self::new(filename, code, reason)
+        }
     }
 }
 
-impl FromRequest for BatchSoundUpload {
-    type Error = actix_web::Error;
-    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
-
-    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
-        // get a future for a Multipart struct from the request
-        let multipart_future = Multipart::from_request(req, payload);
-
-        // As this is not an async function, we cannot use 'await'.
-        // Instead, we create future from this async block and return a pinned Box containing our future.
-        // This is because currently, traits cannot declare async functions, so instead the FromRequest trait declares a non-async function which returns a Future instead.
-        let future = async {
-            // Inside of this async block we are able to use 'await'
-            let multipart = multipart_future.await?;
-
-            // Await our async function containing the actual logic
-            Self::from_multipart(multipart).await
-        };
+/// How a staged file is checked and stored.
+pub struct UploadOptions {
+    pub slugs: Vec<String>,
+    /// Sounds longer than this are refused
+    pub max_duration_seconds: f64,
+    /// Strips leading and trailing silence quieter than this
+    pub trim_silence_below_db: Option<f64>,
+    pub duplicate_policy: DuplicatePolicy,
+    /// Loudness the playback rendition is brought to
+    pub loudness_target_lufs: f64,
+}
 
-        Box::pin(future)
-    }
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct UploadResponse {
+    successful: Vec<UploadSuccess>,
+    failed: Vec<UploadFailure>,
+    tags: Vec<String>,
 }
 
 #[post("/upload")]
@@ -140,44 +126,115 @@ pub async fn upload_handler(
 ) -> Result<HttpResponse, Error> {
     let audio_folder_path = Path::new(&data.audio_folder_path);
     let mut successful_uploads: Vec<UploadSuccess> = vec![];
-    let mut failed_uploads: Vec<UploadFailure> = vec![];
+    let mut failed_uploads: Vec<UploadFailure> = payload.rejected;
+    let tags = normalize_slugs(payload.tags);
 
     for sound_upload in payload.sounds.into_iter() {
         let database_pool = data.database_pool.clone();
         let upload_result = upload_payload_file(
-            sound_upload.file_content,
+            sound_upload.sound,
             &sound_upload.filename,
+            &sound_upload.name,
             audio_folder_path,
             database_pool,
-            payload.tags.clone(),
+            UploadOptions {
+                slugs: sound_upload.tags,
+                max_duration_seconds: data.upload_limits.max_duration_seconds,
+                trim_silence_below_db: if sound_upload.trim_silence {
+                    Some(data.silence_threshold_db)
+                } else {
+                    None
+                },
+                duplicate_policy: data.duplicate_policy,
+                loudness_target_lufs: data.loudness_target_lufs,
+            },
         )
         .await;
 
         match upload_result {
             Ok(successful) => successful_uploads.push(successful),
-            Err(failure) => failed_uploads.push(UploadFailure {
-                filename: sound_upload.filename.to_string(),
-                reason: failure.to_string(),
-            }),
+            Err(failure) => failed_uploads.push(failure),
         }
     }
 
+    if !successful_uploads.is_empty() {
+        Broker::<SystemBroker>::issue_async(WsSoundsChanged {});
+    }
+
     Ok(HttpResponse::Ok().json(UploadResponse {
         successful: successful_uploads,
         failed: failed_uploads,
-        tags: payload.tags,
+        tags,
     }))
 }
 
-async fn upload_payload_file(
-    file_content: Vec<Bytes>,
+pub async fn upload_payload_file(
+    sound: StagedSound,
     filename: &str,
+    name: &str,
     audio_folder_path: &Path,
     database_pool: DatabasePool,
-    slugs: Vec<String>,
-) -> Result<UploadSuccess, Error> {
-    let original_filename = Path::new(filename);
-    let (memory_file, file_type, file_hash) = validate_sound(file_content).await?;
+    options: UploadOptions,
+) -> Result<UploadSuccess, UploadFailure> {
+    let internal_error = |reason: &dyn ToString| {
+        UploadFailure::new(
+            filename,
+            UploadFailureCode::InternalError,
+            reason.to_string(),
+        )
+    };
+
+    let (extension, metadata) =
+        validate_sound(&sound)
+            .await
+            .map_err(|reason| match reason.kind() {
+                ErrorKind::InvalidData => {
+                    UploadFailure::new(filename, UploadFailureCode::InvalidFileType, reason)
+                }
+                _ => internal_error(&reason),
+            })?;
+
+    let duration_ms = metadata.duration_ms.ok_or_else(|| {
+        UploadFailure::new(
+            filename,
+            UploadFailureCode::InvalidFileType,
+            "Failed to read the audio duration.",
+        )
+    })?;
+
+    if duration_ms as f64 / 1000.0 > options.max_duration_seconds {
+        return Err(UploadFailure::new(
+            filename,
+            UploadFailureCode::DurationTooLong,
+            format!(
+                "Sound is longer than the limit of {} seconds.",
+                options.max_duration_seconds
+            ),
+        ));
+    }
+
+    verify_decodes(sound.path())
+        .await
+        .map_err(|reason| UploadFailure::new(filename, UploadFailureCode::Undecodable, reason))?;
+
+    let (sound, trimmed) = match options.trim_silence_below_db {
+        Some(threshold_db) => trim_silence(
+            sound,
+            extension,
+            duration_ms,
+            audio_folder_path,
+            threshold_db,
+        )
+        .await
+        .map_err(|reason| internal_error(&reason))?,
+        None => (sound, None),
+    };
+    // Trimming changes the duration, the file is probed again once stored
+    let metadata = match trimmed {
+        Some(_) => None,
+        None => Some(metadata),
+    };
+    let file_hash = sound.file_hash.clone();
 
     let file_hash_clone = file_hash.clone();
     let database_pool_clone = database_pool.clone();
@@ -188,50 +245,186 @@ async fn upload_payload_file(
 
         fetch_sound_by_hash(file_hash_clone, &database_connection)
     })
-    .await?;
+    .await
+    .map_err(|reason| internal_error(&reason))?;
+
+    if let Some(existing_sound) = sound_hash_match {
+        return Err(UploadFailure::duplicate(
+            filename,
+            UploadFailureCode::AlreadyExists,
+            "File already exists",
+            existing_sound.id,
+        ));
+    }
+
+    /*
+     * The hash only catches byte for byte copies, the fingerprint
+     * also catches the same clip encoded in another way.
+     */
+    let fingerprint = match compute_fingerprint(sound.path()).await {
+        Ok(fingerprint) => Some(fingerprint),
+        Err(reason) => {
+            error!(
+                "Failed to fingerprint {}, skipping the duplicate check. Reason: {:?}",
+                filename, reason
+            );
+            None
+        }
+    };
 
-    if sound_hash_match.is_some() {
-        return Err(std::io::Error::new(ErrorKind::AlreadyExists, "File already exists").into());
+    let duplicate_of = match (options.duplicate_policy, fingerprint.clone()) {
+        (DuplicatePolicy::Off, _) | (_, None) => None,
+        (_, Some(fingerprint)) => {
+            let database_pool = database_pool.clone();
+
+            web::block(move || {
+                let database_connection = database_pool
+                    .get()
+                    .expect("couldn't get db connection from pool");
+
+                fetch_fingerprints_by_length(
+                    comparable_lengths(fingerprint.len()),
+                    &database_connection,
+                )
+                .map(|sounds| find_near_duplicate(&fingerprint, &sounds))
+            })
+            .await
+            .map_err(|reason| internal_error(&reason))?
+            .map_err(|reason| internal_error(&reason))?
+        }
+    };
+
+    if let (DuplicatePolicy::Reject, Some(existing_sound_id)) =
+        (options.duplicate_policy, duplicate_of.clone())
+    {
+        return Err(UploadFailure::duplicate(
+            filename,
+            UploadFailureCode::NearDuplicate,
+            "Sound is a near duplicate of an existing sound",
+            existing_sound_id,
+        ));
     }
 
-    let extension = file_type.extension();
     let file_name = Uuid::new_v4().to_string();
 
-    let _ = save_sound_as_file(
-        memory_file,
-        file_name.clone(),
-        extension.to_string(),
+    let audio_path = sound
+        .persist(&file_name, extension, audio_folder_path)
+        .await
+        .map_err(|reason| internal_error(&reason))?;
+
+    let processed = process_sound_file(
+        &audio_path,
+        &file_name,
+        &file_hash,
         audio_folder_path,
+        metadata,
+        options.loudness_target_lufs,
     )
-    .await?;
-
-    let sound_name = original_filename
-        .file_stem()
-        .expect("Filename to have a basename defined")
-        .to_str()
-        .unwrap()
-        .to_string();
+    .await;
 
     let sound_record = Sound {
         id: Uuid::new_v4().to_string(),
-        name: sound_name,
+        name: name.to_string(),
         file_name,
         file_hash,
         extension: extension.to_string(),
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() as i64)
+            .unwrap_or_default(),
+        play_count: 0,
+        playback_file_name: processed.playback_file_name,
+        loudness_lufs: processed.loudness_lufs,
+        true_peak_dbtp: processed.true_peak_dbtp,
+        duration_ms: processed.metadata.duration_ms,
+        sample_rate: processed.metadata.sample_rate,
+        channels: processed.metadata.channels,
+        codec: processed.metadata.codec,
+        original_file_name: None,
+        original_extension: None,
+        original_file_hash: None,
+        fingerprint: fingerprint.map(|fingerprint| encode_fingerprint(&fingerprint)),
     };
 
     let insertable = sound_record.clone();
-    web::block(move || {
+    let inserted = web::block(move || {
         let database_connection = database_pool
             .get()
             .expect("Failed to get db connection from db pool");
 
-        insert_sound(insertable, slugs, &database_connection);
+        insert_sound(insertable, options.slugs, &database_connection)
     })
-    .await?;
+    .await
+    .map_err(|reason| internal_error(&reason))
+    .and_then(|result| result.map_err(|reason| internal_error(&reason)));
+
+    if let Err(failure) = inserted {
+        // Files of a sound that couldn't be saved would never be cleaned up otherwise
+        if let Err(reason) = remove_derived_files(
+            &sound_record.file_name,
+            sound_record.playback_file_name,
+            audio_folder_path,
+        )
+        .await
+        {
+            error!(
+                "Failed to remove derived files of {}. Reason: {:?}",
+                filename, reason
+            );
+        }
+
+        if let Err(reason) = remove_sound_file(
+            sound_record.file_name,
+            sound_record.extension,
+            audio_folder_path,
+        )
+        .await
+        {
+            error!(
+                "Failed to remove audio file of {}. Reason: {:?}",
+                filename, reason
+            );
+        }
+
+        return Err(failure);
+    }
 
     Ok(UploadSuccess {
         id: sound_record.id.clone(),
         filename: filename.to_string(),
+        trimmed,
+        duplicate_of,
     })
 }
+
+/// Strips the leading and trailing silence of a staged sound, staging the
+/// trimmed audio in its place. Sounds without silence are returned as is.
+async fn trim_silence(
+    sound: StagedSound,
+    extension: &str,
+    duration_ms: i64,
+    audio_folder_path: &Path,
+    threshold_db: f64,
+) -> Result<(StagedSound, Option<SilenceTrim>), std::io::Error> {
+    let trim = match detect_silence(sound.path(), duration_ms, threshold_db).await? {
+        Some(trim) => trim,
+        None => return Ok((sound, None)),
+    };
+
+    let trimmed_path = TemporaryPath::with_extension(audio_folder_path, extension);
+
+    let edit = SoundEdit {
+        start_ms: trim.leading_ms,
+        end_ms: duration_ms - trim.trailing_ms,
+        fade_in_ms: 0,
+        fade_out_ms: 0,
+    };
+
+    apply_sound_edit(sound.path(), trimmed_path.path(), edit).await?;
+
+    let trimmed_sound = web::block(move || StagedSound::from_file(trimmed_path))
+        .await
+        .map_err(|reason| std::io::Error::other(reason.to_string()))??;
+
+    Ok((trimmed_sound, Some(trim)))
+}
@@ -0,0 +1,337 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    future::Future,
+    path::Path,
+    pin::Pin,
+};
+
+use actix_multipart::{Field, Multipart, MultipartError};
+use actix_web::{
+    dev::Payload, http::StatusCode, web::Data, FromRequest, HttpRequest, HttpResponse,
+    ResponseError,
+};
+use serde::{Deserialize, Serialize};
+use serenity::futures::TryStreamExt;
+
+use crate::{
+    actions::fs::{StagedSound, StagedSoundWriter},
+    app_state::AppState,
+};
+
+use super::{UploadFailure, UploadFailureCode};
+
+/// Text fields are small JSON documents, anything
+/// bigger than this is not a valid request.
+const MAX_TEXT_FIELD_BYTES: usize = 64 * 1024;
+
+/// A malformed `POST /upload` request, answered with a 400.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadPayloadError {
+    message: String,
+    field: Option<String>,
+}
+
+impl UploadPayloadError {
+    fn new(message: impl ToString, field: Option<&str>) -> Self {
+        Self {
+            message: message.to_string(),
+            field: field.map(String::from),
+        }
+    }
+}
+
+impl fmt::Display for UploadPayloadError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{}", self.message)
+    }
+}
+
+impl ResponseError for UploadPayloadError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::BadRequest().json(self)
+    }
+}
+
+impl From<MultipartError> for UploadPayloadError {
+    fn from(reason: MultipartError) -> Self {
+        Self::new(format!("Malformed multipart payload: {}", reason), None)
+    }
+}
+
+/// Per file overrides sent in the `metadata` field,
+/// keyed by the uploaded file name.
+#[derive(Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+struct SoundUploadMetadata {
+    name: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    trim_silence: Option<bool>,
+}
+
+// https://gist.github.com/Tarkin25/b6274a8a33baa6a72d7763e298f1fb8f
+pub struct SoundUpload {
+    pub filename: String,
+    /// Display name of the sound, the file stem unless overridden
+    pub name: String,
+    /// Tags sent for every file along with the ones sent for this file
+    pub tags: Vec<String>,
+    /// Whether leading and trailing silence should be stripped
+    pub trim_silence: bool,
+    pub sound: StagedSound,
+}
+
+/// Multipart payload of `POST /upload`. Fields can come in any order:
+///
+/// - every field with a file name is a sound
+/// - `tags` is a JSON list of tags applied to every sound
+/// - `metadata` is a JSON object with a `name`, `tags` and `trimSilence` for each file name,
+///   so file names must be unique within a request
+/// - `trimSilence` is a JSON boolean enabling silence trimming for every sound
+pub struct BatchSoundUpload {
+    pub sounds: Vec<SoundUpload>,
+    /// Files refused while reading the request because of the upload limits
+    pub rejected: Vec<UploadFailure>,
+    pub tags: Vec<String>,
+}
+
+struct ReceivedSound {
+    filename: String,
+    sound: StagedSound,
+}
+
+impl BatchSoundUpload {
+    async fn read_string(field: &mut Field, field_key: &str) -> Result<String, UploadPayloadError> {
+        let mut content: Vec<u8> = Vec::new();
+
+        while let Some(chunk) = field.try_next().await? {
+            if content.len() + chunk.len() > MAX_TEXT_FIELD_BYTES {
+                return Err(UploadPayloadError::new(
+                    format!("Field is bigger than {} bytes.", MAX_TEXT_FIELD_BYTES),
+                    Some(field_key),
+                ));
+            }
+
+            content.extend_from_slice(&chunk);
+        }
+
+        String::from_utf8(content)
+            .map_err(|_| UploadPayloadError::new("Field is not valid utf-8.", Some(field_key)))
+    }
+
+    async fn read_json<T: for<'de> Deserialize<'de>>(
+        field: &mut Field,
+        field_key: &str,
+    ) -> Result<T, UploadPayloadError> {
+        let content = Self::read_string(field, field_key).await?;
+
+        serde_json::from_str(&content).map_err(|reason| {
+            UploadPayloadError::new(
+                format!("Field is not valid JSON: {}", reason),
+                Some(field_key),
+            )
+        })
+    }
+
+    /// Consumes the rest of a field that won't be stored.
+    async fn drain(field: &mut Field) -> Result<(), UploadPayloadError> {
+        while field.try_next().await?.is_some() {}
+
+        Ok(())
+    }
+
+    async fn from_multipart(
+        mut multipart: Multipart,
+        data: Data<AppState>,
+    ) -> Result<Self, actix_web::Error> {
+        let audio_folder_path = Path::new(&data.audio_folder_path);
+        let limits = data.upload_limits;
+        let mut tags: Vec<String> = Vec::new();
+        let mut trim_silence = false;
+        let mut metadata: HashMap<String, SoundUploadMetadata> = HashMap::new();
+        let mut received: Vec<ReceivedSound> = Vec::new();
+        let mut rejected: Vec<UploadFailure> = Vec::new();
+        let mut filenames: HashSet<String> = HashSet::new();
+        let mut file_count = 0;
+
+        while let Some(mut field) = multipart
+            .try_next()
+            .await
+            .map_err(UploadPayloadError::from)?
+        {
+            // A multipart/form-data stream has to contain `content_disposition`
+            // this is where we'll be able to fetch the file name and
+            // the key name for other parts of the payload
+            let content_disposition = field.content_disposition().clone();
+
+            let field_key = match content_disposition.get_name() {
+                Some(field_key) => field_key.to_string(),
+                None => {
+                    return Err(
+                        UploadPayloadError::new("Every field must have a name.", None).into(),
+                    )
+                }
+            };
+
+            let filename = match content_disposition.get_filename() {
+                Some(filename) => filename.to_string(),
+                None => {
+                    match field_key.as_str() {
+                        "tags" => tags = Self::read_json(&mut field, &field_key).await?,
+                        "metadata" => metadata = Self::read_json(&mut field, &field_key).await?,
+                        "trimSilence" => {
+                            trim_silence = Self::read_json(&mut field, &field_key).await?
+                        }
+                        _ => Self::drain(&mut field).await?,
+                    }
+
+                    continue;
+                }
+            };
+
+            if filename.trim().is_empty() {
+                return Err(UploadPayloadError::new(
+                    "Files must have a file name.",
+                    Some(&field_key),
+                )
+                .into());
+            }
+
+            /*
+             * `metadata` is keyed by file name, so a second file
+             * with the same name couldn't be told apart from the first
+             */
+            if !filenames.insert(filename.clone()) {
+                Self::drain(&mut field).await?;
+                rejected.push(UploadFailure::new(
+                    &filename,
+                    UploadFailureCode::DuplicateFilename,
+                    "Another file in the request has the same file name.",
+                ));
+                continue;
+            }
+
+            file_count += 1;
+
+            if file_count > limits.max_files {
+                Self::drain(&mut field).await?;
+                rejected.push(UploadFailure::new(
+                    &filename,
+                    UploadFailureCode::TooManyFiles,
+                    format!("Only {} files can be uploaded at once.", limits.max_files),
+                ));
+                continue;
+            }
+
+            /*
+             * Files are streamed to disk as they arrive
+             * instead of being buffered in memory
+             */
+            let mut writer =
+                StagedSoundWriter::create(audio_folder_path, limits.max_file_bytes).await?;
+            let mut stage_error = None;
+
+            while let Some(chunk) = field.try_next().await.map_err(UploadPayloadError::from)? {
+                if let Err(reason) = writer.write_chunk(&chunk).await {
+                    stage_error = Some(reason);
+                    break;
+                }
+            }
+
+            if let Some(reason) = stage_error {
+                Self::drain(&mut field).await?;
+                rejected.push(UploadFailure::staging(&filename, reason));
+                continue;
+            }
+
+            let sound = writer.finish().await?;
+            received.push(ReceivedSound { filename, sound });
+        }
+
+        /*
+         * Metadata can arrive after the files it
+         * describes, so it's only applied at the end.
+         */
+        let mut sounds: Vec<SoundUpload> = Vec::new();
+
+        for ReceivedSound { filename, sound } in received {
+            let sound_metadata = metadata.get(&filename).cloned().unwrap_or_default();
+            let name = sound_metadata
+                .name
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .or_else(|| {
+                    Path::new(&filename)
+                        .file_stem()
+                        .and_then(|stem| stem.to_str())
+                        .map(|stem| stem.trim().to_string())
+                        .filter(|stem| !stem.is_empty())
+                });
+
+            let name = match name {
+                Some(name) => name,
+                None => {
+                    rejected.push(UploadFailure::new(
+                        &filename,
+                        UploadFailureCode::InvalidName,
+                        "Sound name can't be empty.",
+                    ));
+                    continue;
+                }
+            };
+
+            let sound_tags = tags
+                .iter()
+                .cloned()
+                .chain(sound_metadata.tags)
+                .collect::<Vec<_>>();
+
+            sounds.push(SoundUpload {
+                filename,
+                name,
+                tags: sound_tags,
+                trim_silence: sound_metadata.trim_silence.unwrap_or(trim_silence),
+                sound,
+            });
+        }
+
+        Ok(BatchSoundUpload {
+            tags,
+            sounds,
+            rejected,
+        })
+    }
+}
+
+impl FromRequest for BatchSoundUpload {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        // get a future for a Multipart struct from the request
+        let multipart_future = Multipart::from_request(req, payload);
+        let data = req
+            .app_data::<Data<AppState>>()
+            .cloned()
+            .expect("AppState to be registered as app data");
+
+        // As this is not an async function, we cannot use 'await'.
+        // Instead, we create future from this async block and return a pinned Box containing our future.
+        // This is because currently, traits cannot declare async functions, so instead the FromRequest trait declares a non-async function which returns a Future instead.
+        let future = async {
+            // Inside of this async block we are able to use 'await'
+            let multipart = multipart_future.await?;
+
+            // Await our async function containing the actual logic
+            Self::from_multipart(multipart, data).await
+        };
+
+        Box::pin(future)
+    }
+}
@@ -0,0 +1,87 @@
+use actix_web::{
+    error::InternalError, http::StatusCode, web::Data, Error, HttpResponse, ResponseError,
+};
+use serde::Serialize;
+
+use crate::{app_state::AppState, discord::actor::VoiceError};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorPayload {
+    message: String,
+}
+
+fn bad_request(message: String) -> Error {
+    InternalError::from_response(
+        message.clone(),
+        HttpResponse::BadRequest().json(ErrorPayload { message }),
+    )
+    .into()
+}
+
+/// Parses a Discord id sent as a string, answering with a 400 naming `field` otherwise.
+pub fn parse_discord_id(id: &str, field: &str) -> Result<u64, Error> {
+    id.parse::<u64>()
+        .map_err(|_| bad_request(format!("`{}` must be a Discord id, got: {}", field, id)))
+}
+
+/// Guild given by the request, `DISCORD_GUILD_ID` when missing.
+pub fn guild_id(guild_id: &Option<String>, data: &Data<AppState>) -> Result<u64, Error> {
+    match (guild_id, data.default_discord_guild_id) {
+        (Some(guild_id), _) => parse_discord_id(guild_id, "guildId"),
+        (None, Some(default_guild_id)) => Ok(default_guild_id),
+        (None, None) => Err(bad_request(
+            "`guildId` is required since no default guild is configured.".to_string(),
+        )),
+    }
+}
+
+impl ResponseError for VoiceError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            VoiceError::NotConnected | VoiceError::Locked | VoiceError::SoundBusy => {
+                StatusCode::CONFLICT
+            }
+            VoiceError::UnknownGuild | VoiceError::UnknownChannel => StatusCode::NOT_FOUND,
+            VoiceError::MissingAudio | VoiceError::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(ErrorPayload {
+            message: self.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::body::to_bytes;
+
+    use super::*;
+
+    #[test]
+    fn voice_errors_map_to_their_status() {
+        for (reason, status) in [
+            (VoiceError::Locked, StatusCode::CONFLICT),
+            (VoiceError::NotConnected, StatusCode::CONFLICT),
+            (VoiceError::UnknownChannel, StatusCode::NOT_FOUND),
+            (VoiceError::MissingAudio, StatusCode::INTERNAL_SERVER_ERROR),
+        ] {
+            assert_eq!(reason.error_response().status(), status, "{:?}", reason);
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_ids_are_bad_requests() {
+        let response = parse_discord_id("general", "channelId")
+            .unwrap_err()
+            .error_response();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            to_bytes(response.into_body()).await.unwrap(),
+            r#"{"message":"`channelId` must be a Discord id, got: general"}"#
+        );
+    }
+}
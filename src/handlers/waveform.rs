@@ -0,0 +1,106 @@
+use std::path::Path;
+
+use actix_web::{
+    get,
+    web::{self, Data},
+    Error, HttpResponse,
+};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::{sounds::fetch_sound_by_id, waveform::load_waveform},
+    app_state::AppState,
+};
+
+#[derive(Deserialize)]
+pub struct WaveformRequestPath {
+    sound_id: String,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+enum WaveformFormat {
+    Json,
+    Binary,
+}
+
+#[derive(Deserialize)]
+pub struct WaveformQuery {
+    format: Option<WaveformFormat>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorPayload {
+    message: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WaveformResponse {
+    sound_id: String,
+    length: usize,
+    peaks: Vec<i8>,
+}
+
+/// Serves the waveform peaks of a sound as interleaved min/max pairs.
+///
+/// `?format=binary` returns the peaks as raw signed bytes instead of JSON.
+#[get("/sounds/{sound_id}/waveform")]
+pub async fn waveform_handler(
+    path: web::Path<WaveformRequestPath>,
+    query: web::Query<WaveformQuery>,
+    data: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let sound_id = path.sound_id.clone();
+    let database_pool = data.database_pool.clone();
+    let sound = web::block(move || {
+        let database_connection = database_pool
+            .get()
+            .expect("couldn't get db connection from pool");
+
+        fetch_sound_by_id(sound_id, &database_connection)
+    })
+    .await?;
+
+    let sound = match sound {
+        Some(sound) => sound,
+        None => {
+            return Ok(HttpResponse::NotFound().json(ErrorPayload {
+                message: format!("Failed to find sound with id: {}", path.sound_id),
+            }));
+        }
+    };
+
+    let audio_folder_path = Path::new(&data.audio_folder_path);
+    let waveform = match load_waveform(&sound, audio_folder_path).await {
+        Ok(waveform) => waveform,
+        Err(reason) => {
+            error!(
+                "Failed to load waveform for sound with id {}. Reason: {:?}",
+                sound.id, reason
+            );
+            return Ok(HttpResponse::InternalServerError().json(ErrorPayload {
+                message: "Server failed to compute the sound waveform.".to_string(),
+            }));
+        }
+    };
+
+    match query.format.unwrap_or(WaveformFormat::Json) {
+        WaveformFormat::Json => Ok(HttpResponse::Ok().json(WaveformResponse {
+            sound_id: sound.id,
+            length: waveform.peaks.len() / 2,
+            peaks: waveform.peaks,
+        })),
+        WaveformFormat::Binary => Ok(HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .body(
+                waveform
+                    .peaks
+                    .into_iter()
+                    .map(|peak| peak as u8)
+                    .collect::<Vec<u8>>(),
+            )),
+    }
+}
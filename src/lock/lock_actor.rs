@@ -1,18 +1,41 @@
-use crate::lock::messages::{GetLockStatus, Lock, LockStatus, Unlock, WsLockSound, WsUnlockSound};
-use actix::{Actor, Context, Handler};
+use crate::lock::messages::{
+    GetLockStatus, LockError, LockStatus, ReleaseSound, ReserveSound, TryLock, Unlock, WsLockSound,
+    WsUnlockSound,
+};
+use actix::{Actor, Addr, AsyncContext, Context, Handler, SpawnHandle};
 use actix_broker::{BrokerIssue, BrokerSubscribe};
 use log::{debug, info};
+use std::{collections::HashSet, time::Duration};
+
+/// Extra time a sound gets to finish playing before its lock expires.
+const LOCK_TIMEOUT_GRACE: Duration = Duration::from_secs(5);
 
 #[derive(Clone)]
 pub struct SoundLockActor {
     status: LockStatus,
+    /// Releases the lock in case the end of the track is never notified
+    timeout: Option<SpawnHandle>,
+    /// Sounds whose files are being removed or replaced
+    reserved: HashSet<String>,
 }
 
 impl SoundLockActor {
     pub fn new() -> Self {
         Self {
             status: LockStatus::new(),
+            timeout: None,
+            reserved: HashSet::new(),
+        }
+    }
+
+    fn unlock(&mut self, ctx: &mut Context<Self>) {
+        if let Some(timeout) = self.timeout.take() {
+            ctx.cancel_future(timeout);
         }
+
+        self.status = LockStatus::new();
+        debug!("set status to {:?}", self.status);
+        self.issue_system_async(WsUnlockSound {});
     }
 }
 
@@ -20,33 +43,55 @@ impl Actor for SoundLockActor {
     type Context = Context<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        self.subscribe_system_async::<Lock>(ctx);
         self.subscribe_system_async::<Unlock>(ctx);
     }
 }
 
-impl Handler<Lock> for SoundLockActor {
-    type Result = ();
+impl Handler<TryLock> for SoundLockActor {
+    type Result = Result<(), LockError>;
+
+    fn handle(&mut self, msg: TryLock, ctx: &mut Context<Self>) -> Self::Result {
+        if self.status.is_locked {
+            return Err(LockError::Locked);
+        }
+
+        if self.reserved.contains(&msg.sound.id) {
+            return Err(LockError::Reserved);
+        }
 
-    fn handle(&mut self, msg: Lock, _ctx: &mut Context<Self>) -> Self::Result {
         info!("handling lock with sound '{}'", msg.sound.name);
+
+        if let Some(timeout) = self.timeout.take() {
+            ctx.cancel_future(timeout);
+        }
+
+        if let Some(duration_ms) = msg.sound.duration_ms {
+            let timeout = Duration::from_millis(duration_ms.max(0) as u64) + LOCK_TIMEOUT_GRACE;
+
+            self.timeout = Some(ctx.run_later(timeout, |actor, ctx| {
+                info!("lock timed out");
+                actor.timeout = None;
+                actor.unlock(ctx);
+            }));
+        }
+
         self.status = LockStatus {
             sound: Some(msg.sound),
             is_locked: true,
         };
         debug!("set status to {:?}", self.status);
         self.issue_system_async(WsLockSound {});
+
+        Ok(())
     }
 }
 
 impl Handler<Unlock> for SoundLockActor {
     type Result = ();
 
-    fn handle(&mut self, _msg: Unlock, _ctx: &mut Context<Self>) -> Self::Result {
+    fn handle(&mut self, _msg: Unlock, ctx: &mut Context<Self>) -> Self::Result {
         info!("handling unlock");
-        self.status = LockStatus::new();
-        debug!("set status to {:?}", self.status);
-        self.issue_system_async(WsUnlockSound {});
+        self.unlock(ctx);
     }
 }
 
@@ -59,3 +104,66 @@ impl Handler<GetLockStatus> for SoundLockActor {
         Some(status)
     }
 }
+
+impl Handler<ReserveSound> for SoundLockActor {
+    type Result = Result<(), LockError>;
+
+    fn handle(&mut self, msg: ReserveSound, _ctx: &mut Context<Self>) -> Self::Result {
+        let is_playing = self.status.is_locked
+            && self
+                .status
+                .sound
+                .as_ref()
+                .is_some_and(|sound| sound.id == msg.sound_id);
+
+        if is_playing {
+            return Err(LockError::Playing);
+        }
+
+        if !self.reserved.insert(msg.sound_id) {
+            return Err(LockError::Reserved);
+        }
+
+        Ok(())
+    }
+}
+
+impl Handler<ReleaseSound> for SoundLockActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReleaseSound, _ctx: &mut Context<Self>) -> Self::Result {
+        self.reserved.remove(&msg.sound_id);
+    }
+}
+
+/// Reservation of a sound's files, released once dropped.
+pub struct SoundReservation {
+    sound_lock_actor_addr: Addr<SoundLockActor>,
+    sound_id: String,
+}
+
+impl SoundReservation {
+    pub async fn reserve(
+        sound_lock_actor_addr: Addr<SoundLockActor>,
+        sound_id: String,
+    ) -> Result<Result<Self, LockError>, actix::MailboxError> {
+        let result = sound_lock_actor_addr
+            .send(ReserveSound {
+                sound_id: sound_id.clone(),
+            })
+            .await?;
+
+        Ok(result.map(|()| Self {
+            sound_lock_actor_addr,
+            sound_id,
+        }))
+    }
+}
+
+impl Drop for SoundReservation {
+    fn drop(&mut self) {
+        self.sound_lock_actor_addr.do_send(ReleaseSound {
+            sound_id: self.sound_id.clone(),
+        });
+    }
+}
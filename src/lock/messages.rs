@@ -25,12 +25,38 @@ pub struct WsLockSound {}
 #[rtype(result = "()")]
 pub struct WsUnlockSound {}
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockError {
+    /// Another sound is playing
+    Locked,
+    /// The sound is the one playing
+    Playing,
+    /// The files of the sound are being removed or replaced
+    Reserved,
+}
+
+/// Locks the sound lock for a sound, unless a sound is
+/// already playing or the sound's files are reserved.
 #[derive(Message, Clone)]
-#[rtype(result = "()")]
-pub struct Lock {
+#[rtype(result = "Result<(), LockError>")]
+pub struct TryLock {
     pub sound: Sound,
 }
 
+/// Keeps a sound from being played while its files are removed
+/// or replaced. Fails when the sound is playing or already reserved.
+#[derive(Message, Clone)]
+#[rtype(result = "Result<(), LockError>")]
+pub struct ReserveSound {
+    pub sound_id: String,
+}
+
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct ReleaseSound {
+    pub sound_id: String,
+}
+
 #[derive(Message, Clone)]
 #[rtype(result = "()")]
 pub struct Unlock;
@@ -3,18 +3,22 @@ extern crate diesel;
 
 mod actions;
 mod app_state;
+mod backfill;
 mod discord;
 mod handlers;
 mod lock;
 pub mod models;
 pub mod schema;
+mod telegram;
+#[cfg(test)]
+mod test_utils;
 mod websocket;
 
 use actix::prelude::*;
 use diesel_migrations::run_pending_migrations;
 use log::info;
 use songbird::{SerenityInit, Songbird};
-use std::env;
+use std::{env, path::Path};
 
 use serenity::{client::Client, framework::StandardFramework};
 use teloxide::prelude::*;
@@ -25,12 +29,36 @@ use actix_web::{middleware::Logger, web, web::Data, App, HttpServer};
 use diesel::r2d2::{ConnectionManager, Pool};
 use diesel::sqlite::SqliteConnection;
 
-use app_state::AppState;
-use discord::{actor::DiscordActor, commands::BOTCOMMANDS_GROUP, DiscordHandler};
+use actions::slugs::normalize_stored_slugs;
+use app_state::{
+    AppState, DuplicatePolicy, ImportLimits, SqliteConnectionCustomizer, UploadLimits,
+    DEFAULT_DUPLICATE_POLICY, DEFAULT_IMPORT_MAX_ARCHIVE_BYTES, DEFAULT_IMPORT_MAX_FILES,
+    DEFAULT_LOUDNESS_TARGET_LUFS, DEFAULT_SILENCE_THRESHOLD_DB,
+    DEFAULT_UPLOAD_MAX_DURATION_SECONDS, DEFAULT_UPLOAD_MAX_FILES, DEFAULT_UPLOAD_MAX_FILE_BYTES,
+};
+use backfill::run_backfill;
+use discord::{actor::DiscordActor, commands::BOTCOMMANDS_GROUP, DatabasePoolKey, DiscordHandler};
 use handlers::{
-    add_tags::add_tags_handler, play_sound::play_sound_handler, sounds::sounds_handler,
+    add_tags::add_tags_handler,
+    delete_sound::delete_sound_handler,
+    discord_guilds::discord_guilds_handler,
+    discord_voice::{join_voice_channel_handler, leave_voice_channel_handler},
+    download_sounds::download_sounds_handler,
+    duplicate_sounds::duplicate_sounds_handler,
+    edit_sound::{edit_sound_handler, revert_sound_handler},
+    get_sound::get_sound_handler,
+    import::import_handler,
+    import_url::import_url_handler,
+    play_sound::play_sound_handler,
+    remove_tags::{remove_tag_handler, remove_tags_handler},
+    search_sounds::search_sounds_handler,
+    sounds::sounds_handler,
+    tags::tags_handler,
+    update_sound::update_sound_handler,
     upload::upload_handler,
+    waveform::waveform_handler,
 };
+use telegram::run_telegram_bot;
 use websocket::sound_lock::sound_lock_handler;
 
 use crate::lock::lock_actor::SoundLockActor;
@@ -42,14 +70,6 @@ async fn main() {
     let logger_env = env_logger::Env::new().default_filter_or("info,tracing::span=off");
     env_logger::init_from_env(logger_env);
 
-    let discord_token =
-        env::var("DISCORD_TOKEN").expect("DISCORD_TOKEN to be set in the environment");
-    let telegram_chat_id =
-        env::var("TELEGRAM_CHAT_ID").expect("TELEGRAM_CHAT_ID to be set in the environment");
-    let discord_guild_id = env::var("DISCORD_GUILD_ID")
-        .expect("DISCORD_GUILD_ID to be set in the environment")
-        .parse::<u64>()
-        .expect("DISCORD_GUILD_ID should be a valid number");
     let database_path =
         env::var("DATABASE_PATH").expect("DATABASE_PATH to be set in the environment");
     let audio_folder_path =
@@ -58,6 +78,95 @@ async fn main() {
         .unwrap_or_else(|_| "false".to_string())
         .parse::<bool>()
         .expect("RUN_PENDING_MIGRATIONS should be a boolean");
+    let upload_limits = UploadLimits {
+        max_file_bytes: env::var("UPLOAD_MAX_FILE_BYTES")
+            .unwrap_or_else(|_| DEFAULT_UPLOAD_MAX_FILE_BYTES.to_string())
+            .parse::<u64>()
+            .expect("UPLOAD_MAX_FILE_BYTES should be a valid number"),
+        max_files: env::var("UPLOAD_MAX_FILES")
+            .unwrap_or_else(|_| DEFAULT_UPLOAD_MAX_FILES.to_string())
+            .parse::<usize>()
+            .expect("UPLOAD_MAX_FILES should be a valid number"),
+        max_duration_seconds: env::var("UPLOAD_MAX_DURATION_SECONDS")
+            .unwrap_or_else(|_| DEFAULT_UPLOAD_MAX_DURATION_SECONDS.to_string())
+            .parse::<f64>()
+            .expect("UPLOAD_MAX_DURATION_SECONDS should be a valid number"),
+    };
+    let import_limits = ImportLimits {
+        max_archive_bytes: env::var("IMPORT_MAX_ARCHIVE_BYTES")
+            .unwrap_or_else(|_| DEFAULT_IMPORT_MAX_ARCHIVE_BYTES.to_string())
+            .parse::<u64>()
+            .expect("IMPORT_MAX_ARCHIVE_BYTES should be a valid number"),
+        max_files: env::var("IMPORT_MAX_FILES")
+            .unwrap_or_else(|_| DEFAULT_IMPORT_MAX_FILES.to_string())
+            .parse::<usize>()
+            .expect("IMPORT_MAX_FILES should be a valid number"),
+    };
+    let loudness_target_lufs = env::var("LOUDNESS_TARGET_LUFS")
+        .unwrap_or_else(|_| DEFAULT_LOUDNESS_TARGET_LUFS.to_string())
+        .parse::<f64>()
+        .expect("LOUDNESS_TARGET_LUFS should be a valid number");
+    let silence_threshold_db = env::var("SILENCE_THRESHOLD_DB")
+        .unwrap_or_else(|_| DEFAULT_SILENCE_THRESHOLD_DB.to_string())
+        .parse::<f64>()
+        .expect("SILENCE_THRESHOLD_DB should be a valid number");
+    let duplicate_policy = env::var("DUPLICATE_POLICY")
+        .map(|duplicate_policy| {
+            duplicate_policy
+                .parse::<DuplicatePolicy>()
+                .expect("DUPLICATE_POLICY should be one of off, warn or reject")
+        })
+        .unwrap_or(DEFAULT_DUPLICATE_POLICY);
+
+    let manager = ConnectionManager::<SqliteConnection>::new(database_path);
+    let database_pool = Pool::builder()
+        .max_size(10)
+        .connection_customizer(Box::new(SqliteConnectionCustomizer))
+        .build(manager)
+        .unwrap();
+
+    if should_run_pending_migrations {
+        let database_connection = database_pool
+            .get()
+            .expect("Failed to acquire db connection from db pool");
+        run_pending_migrations(&database_connection).expect("Failed to run pending migrations.");
+    }
+
+    // Tags stored before slugs were normalized
+    let normalized_tag_count = normalize_stored_slugs(
+        &database_pool
+            .get()
+            .expect("Failed to acquire db connection from db pool"),
+    )
+    .expect("Failed to normalize stored tag slugs.");
+    if normalized_tag_count > 0 {
+        info!("Normalized {} stored tag slugs", normalized_tag_count);
+    }
+
+    /*
+     * `backfill` processes the existing library
+     * and exits without starting the bots.
+     */
+    if env::args().nth(1).as_deref() == Some("backfill") {
+        run_backfill(
+            database_pool,
+            Path::new(&audio_folder_path),
+            loudness_target_lufs,
+        )
+        .await
+        .expect("Failed to backfill sounds");
+        return;
+    }
+
+    let discord_token =
+        env::var("DISCORD_TOKEN").expect("DISCORD_TOKEN to be set in the environment");
+    let telegram_chat_id =
+        env::var("TELEGRAM_CHAT_ID").expect("TELEGRAM_CHAT_ID to be set in the environment");
+    let default_discord_guild_id = env::var("DISCORD_GUILD_ID").ok().map(|discord_guild_id| {
+        discord_guild_id
+            .parse::<u64>()
+            .expect("DISCORD_GUILD_ID should be a valid number")
+    });
 
     let framework = StandardFramework::new()
         .configure(|c| c.prefix("~"))
@@ -67,20 +176,22 @@ async fn main() {
     let sound_lock_actor_addr = SoundLockActor::new().start();
 
     let songbird = Songbird::serenity();
-    let discord_actor_addr = DiscordActor {
-        discord_guild_id,
-        songbird: songbird.clone(),
-        sound_lock_actor_addr: sound_lock_actor_addr.clone(),
-    }
-    .start();
 
     let mut client = Client::builder(&discord_token)
         .event_handler(event_handler)
         .framework(framework)
-        .register_songbird_with(songbird)
+        .register_songbird_with(songbird.clone())
+        .type_map_insert::<DatabasePoolKey>(database_pool.clone())
         .await
         .expect("Discord client instance to be created.");
 
+    let discord_actor_addr = DiscordActor {
+        songbird,
+        cache: client.cache_and_http.cache.clone(),
+        sound_lock_actor_addr: sound_lock_actor_addr.clone(),
+    }
+    .start();
+
     let discord_client_thread = actix_web::rt::spawn(async move {
         client
             .start()
@@ -88,15 +199,11 @@ async fn main() {
             .map_err(|reason| eprintln!("Discord client connection was terminated: {:?}", reason))
     });
 
-    let manager = ConnectionManager::<SqliteConnection>::new(database_path);
-    let database_pool = Pool::builder().max_size(10).build(manager).unwrap();
-
-    if should_run_pending_migrations {
-        let database_connection = database_pool
-            .get()
-            .expect("Failed to acquire db connection from db pool");
-        run_pending_migrations(&database_connection).expect("Failed to run pending migrations.");
-    }
+    let telegram_bot_thread = actix_web::rt::spawn(run_telegram_bot(
+        Bot::from_env(),
+        database_pool.clone(),
+        telegram_chat_id.clone(),
+    ));
 
     let http_server_thread = HttpServer::new(move || {
         let telegram_bot = Bot::from_env();
@@ -110,13 +217,18 @@ async fn main() {
 
         let app_data = Data::new(AppState {
             app_name,
-            discord_guild_id,
+            default_discord_guild_id,
             telegram_bot,
             telegram_chat_id: telegram_chat_id.clone(),
             discord_actor_addr: discord_actor_addr.clone(),
             sound_lock_actor_addr: sound_lock_actor_addr.clone(),
             database_pool: database_pool.clone(),
             audio_folder_path: audio_folder_path.clone(),
+            upload_limits,
+            import_limits,
+            loudness_target_lufs,
+            silence_threshold_db,
+            duplicate_policy,
         });
         let websocket_handler = web::resource("/ws").to(sound_lock_handler);
 
@@ -126,9 +238,26 @@ async fn main() {
             .app_data(app_data)
             .service(websocket_handler)
             .service(sounds_handler)
+            .service(search_sounds_handler)
+            .service(duplicate_sounds_handler)
+            .service(get_sound_handler)
+            .service(waveform_handler)
+            .service(download_sounds_handler)
             .service(upload_handler)
+            .service(import_handler)
+            .service(import_url_handler)
             .service(play_sound_handler)
+            .service(discord_guilds_handler)
+            .service(join_voice_channel_handler)
+            .service(leave_voice_channel_handler)
             .service(add_tags_handler)
+            .service(delete_sound_handler)
+            .service(update_sound_handler)
+            .service(edit_sound_handler)
+            .service(revert_sound_handler)
+            .service(remove_tag_handler)
+            .service(remove_tags_handler)
+            .service(tags_handler)
             .service(Files::new("/assets", audio_folder_path.clone()))
     })
     .bind("0.0.0.0:8080")
@@ -141,6 +270,7 @@ async fn main() {
      */
     tokio::select! {
         _ = discord_client_thread => 0,
+        _ = telegram_bot_thread => 0,
         _ = http_server_thread => 0,
     };
 
@@ -1,6 +1,9 @@
 use crate::schema::{sounds, tags};
 
-use diesel::Queryable;
+use diesel::{
+    sql_types::{BigInt, Text},
+    Queryable,
+};
 use serde::{Deserialize, Serialize};
 #[derive(
     Queryable, Associations, Identifiable, Deserialize, Serialize, Insertable, Clone, Debug,
@@ -13,6 +16,49 @@ pub struct Sound {
     pub extension: String,
     pub file_name: String,
     pub file_hash: String,
+    /// Unix timestamp of the upload, 0 for sounds uploaded before it was stored
+    pub created_at: i64,
+    pub play_count: i32,
+    /// DCA rendition used for voice playback, if it was transcoded
+    #[serde(skip)]
+    pub playback_file_name: Option<String>,
+    /// Integrated loudness of the original file, if it was measured
+    pub loudness_lufs: Option<f64>,
+    pub duration_ms: Option<i64>,
+    pub sample_rate: Option<i32>,
+    pub channels: Option<i32>,
+    pub codec: Option<String>,
+    /// File the sound was edited from, kept so the edit can be reverted
+    pub original_file_name: Option<String>,
+    pub original_extension: Option<String>,
+    pub original_file_hash: Option<String>,
+    /// Encoded audio fingerprint, used to find near duplicates,
+    /// only loaded by the `fetch_fingerprints*` queries
+    #[serde(skip)]
+    pub fingerprint: Option<Vec<u8>>,
+    /// True peak of the original file, if it was measured
+    pub true_peak_dbtp: Option<f64>,
+}
+
+/// Replaces the audio file of a sound along with everything derived from it.
+#[derive(AsChangeset, Clone, Debug)]
+#[table_name = "sounds"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct SoundFileChangeset {
+    pub extension: String,
+    pub file_name: String,
+    pub file_hash: String,
+    pub playback_file_name: Option<String>,
+    pub loudness_lufs: Option<f64>,
+    pub duration_ms: Option<i64>,
+    pub sample_rate: Option<i32>,
+    pub channels: Option<i32>,
+    pub codec: Option<String>,
+    pub original_file_name: Option<String>,
+    pub original_extension: Option<String>,
+    pub original_file_hash: Option<String>,
+    pub fingerprint: Option<Vec<u8>>,
+    pub true_peak_dbtp: Option<f64>,
 }
 
 #[derive(Queryable, Associations, Identifiable, Deserialize, Serialize, Insertable, Clone)]
@@ -33,5 +79,33 @@ pub struct SoundWithTags {
     pub extension: String,
     pub file_name: String,
     pub file_hash: String,
+    /// Unix timestamp of the upload, 0 for sounds uploaded before it was stored
+    pub created_at: i64,
+    pub play_count: i32,
+    #[serde(skip)]
+    pub playback_file_name: Option<String>,
+    pub duration_ms: Option<i64>,
+    pub sample_rate: Option<i32>,
+    pub channels: Option<i32>,
+    pub codec: Option<String>,
+    /// Whether the sound was trimmed or faded and can be reverted
+    pub edited: bool,
     pub tags: Vec<String>,
 }
+
+#[derive(QueryableByName, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TagWithCount {
+    #[sql_type = "Text"]
+    pub slug: String,
+    #[sql_type = "BigInt"]
+    pub count: i64,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SoundsPage {
+    pub items: Vec<SoundWithTags>,
+    pub total: i64,
+    pub next_cursor: Option<String>,
+}
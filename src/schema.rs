@@ -5,6 +5,19 @@ table! {
         extension -> Text,
         file_name -> Text,
         file_hash -> Text,
+        created_at -> BigInt,
+        play_count -> Integer,
+        playback_file_name -> Nullable<Text>,
+        loudness_lufs -> Nullable<Double>,
+        duration_ms -> Nullable<BigInt>,
+        sample_rate -> Nullable<Integer>,
+        channels -> Nullable<Integer>,
+        codec -> Nullable<Text>,
+        original_file_name -> Nullable<Text>,
+        original_extension -> Nullable<Text>,
+        original_file_hash -> Nullable<Text>,
+        fingerprint -> Nullable<Binary>,
+        true_peak_dbtp -> Nullable<Double>,
     }
 }
 
@@ -0,0 +1,97 @@
+use log::{error, info};
+use teloxide::{prelude::*, utils::command::BotCommands};
+
+use crate::{
+    actions::search::{search_sounds, SEARCH_RESULT_LIMIT},
+    app_state::DatabasePool,
+};
+
+/// Chat the bot sends sounds to, the only one it answers commands in.
+#[derive(Clone)]
+struct TelegramChatId(String);
+
+impl TelegramChatId {
+    fn matches(&self, chat: &teloxide::types::Chat) -> bool {
+        chat.id.to_string() == self.0
+            || chat
+                .username()
+                .is_some_and(|username| format!("@{}", username) == self.0)
+    }
+}
+
+#[derive(BotCommands, Clone)]
+#[command(
+    rename_rule = "lowercase",
+    description = "These commands are supported:"
+)]
+enum TelegramCommand {
+    #[command(description = "list the commands.")]
+    Help,
+    #[command(description = "search sounds by name or tag.")]
+    Search(String),
+}
+
+async fn answer(
+    bot: Bot,
+    msg: Message,
+    command: TelegramCommand,
+    database_pool: DatabasePool,
+    chat_id: TelegramChatId,
+) -> ResponseResult<()> {
+    if !chat_id.matches(&msg.chat) {
+        return Ok(());
+    }
+
+    let reply = match command {
+        TelegramCommand::Help => TelegramCommand::descriptions().to_string(),
+        TelegramCommand::Search(search) if search.trim().is_empty() => {
+            "Must provide something to search for".to_string()
+        }
+        TelegramCommand::Search(search) => {
+            let result = tokio::task::spawn_blocking(move || {
+                let database_connection = database_pool
+                    .get()
+                    .expect("couldn't get db connection from pool");
+
+                search_sounds(&search, SEARCH_RESULT_LIMIT, &database_connection)
+            })
+            .await;
+
+            match result {
+                Ok(Ok(sounds)) if sounds.is_empty() => "No sounds found".to_string(),
+                Ok(Ok(sounds)) => sounds
+                    .iter()
+                    .map(|sound| format!("{} ({})", sound.name, sound.id))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                Ok(Err(reason)) => {
+                    error!("Failed to search sounds in database. Reason: {:?}", reason);
+                    "Failed to search sounds".to_string()
+                }
+                Err(reason) => {
+                    error!("Failed to search sounds. Reason: {:?}", reason);
+                    "Failed to search sounds".to_string()
+                }
+            }
+        }
+    };
+
+    bot.send_message(msg.chat.id, reply).await?;
+
+    Ok(())
+}
+
+/// Answers bot commands sent to the Telegram chat, until the process stops.
+pub async fn run_telegram_bot(bot: Bot, database_pool: DatabasePool, chat_id: String) {
+    info!("Telegram bot is listening for commands");
+
+    let handler = Update::filter_message()
+        .filter_command::<TelegramCommand>()
+        .endpoint(answer);
+
+    Dispatcher::builder(bot, handler)
+        .dependencies(dptree::deps![database_pool, TelegramChatId(chat_id)])
+        .build()
+        .dispatch()
+        .await;
+}
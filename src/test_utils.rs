@@ -0,0 +1,60 @@
+use std::{fs, path::PathBuf};
+
+use diesel::prelude::*;
+use diesel_migrations::{find_migrations_directory, run_pending_migrations_in_directory};
+use uuid::Uuid;
+
+use crate::models::Sound;
+
+/// Temporary folder removed with everything in it once dropped.
+pub struct TestFolder(pub PathBuf);
+
+impl TestFolder {
+    pub fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("muminst-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TestFolder {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// In-memory database set up like the ones of the connection pool.
+pub fn database_connection() -> SqliteConnection {
+    let database_connection = SqliteConnection::establish(":memory:").unwrap();
+    run_pending_migrations_in_directory(
+        &database_connection,
+        &find_migrations_directory().unwrap(),
+        &mut std::io::sink(),
+    )
+    .unwrap();
+    database_connection
+}
+
+/// Sound named after its id, without any of the optional ingest data.
+pub fn sound(id: &str) -> Sound {
+    Sound {
+        id: id.to_string(),
+        name: id.to_string(),
+        extension: "mp3".to_string(),
+        file_name: id.to_string(),
+        file_hash: id.to_string(),
+        created_at: 0,
+        play_count: 0,
+        playback_file_name: None,
+        loudness_lufs: None,
+        duration_ms: None,
+        sample_rate: None,
+        channels: None,
+        codec: None,
+        original_file_name: None,
+        original_extension: None,
+        original_file_hash: None,
+        fingerprint: None,
+        true_peak_dbtp: None,
+    }
+}
@@ -0,0 +1,7 @@
+use actix::Message;
+
+/// Issued whenever sounds are added, changed or removed so that
+/// connected clients know they should refetch the library.
+#[derive(Clone, Message)]
+#[rtype(result = "()")]
+pub struct WsSoundsChanged {}
@@ -1,6 +1,7 @@
 use crate::app_state::AppState;
 use crate::lock::lock_actor::SoundLockActor;
 use crate::lock::messages::{GetLockStatus, WsLockSound, WsUnlockSound};
+use crate::websocket::messages::WsSoundsChanged;
 use actix::prelude::*;
 use actix::{Actor, StreamHandler};
 use actix_broker::{Broker, BrokerSubscribe, SystemBroker};
@@ -55,6 +56,7 @@ impl Actor for SoundLockWsActor {
         self.heartbeat(ctx);
         self.subscribe_system_async::<WsLockSound>(ctx);
         self.subscribe_system_async::<WsUnlockSound>(ctx);
+        self.subscribe_system_async::<WsSoundsChanged>(ctx);
 
         let sound_lock_actor_addr_clone = self.sound_lock_actor_addr.clone();
 
@@ -130,6 +132,15 @@ impl Handler<WsUnlockSound> for SoundLockWsActor {
     }
 }
 
+impl Handler<WsSoundsChanged> for SoundLockWsActor {
+    type Result = ();
+
+    fn handle(&mut self, _msg: WsSoundsChanged, ctx: &mut Self::Context) -> Self::Result {
+        info!("sending sounds changed to client");
+        ctx.text("{ \"soundsChanged\": true }")
+    }
+}
+
 pub async fn sound_lock_handler(
     req: HttpRequest,
     stream: web::Payload,